@@ -0,0 +1,7 @@
+fn main() {
+    // The `hot_reload` example calls SDK functions which are provided by the simulator host when
+    // it's loaded. Linux allows undefined symbols in libraries by default, but macOS doesn't.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("macos") {
+        println!("cargo:rustc-link-arg-examples=-Wl,-undefined,dynamic_lookup");
+    }
+}
@@ -11,12 +11,18 @@ macro_rules! create_main {
                     .with_env_filter(::tracing_subscriber::EnvFilter::from_default_env())
                     .init();
 
-                ::vex_sdk_desktop::run_simulator(move || {
-                    ::vexide::runtime::block_on(async move {
-                        $entrypoint(p).await;
+                // The entrypoint runs again each time the program is restarted, and each run
+                // takes the peripherals afresh.
+                drop(p);
+
+                let exit_code = ::vex_sdk_desktop::run_simulator(|| {
+                    ::vexide::runtime::block_on(async {
+                        $entrypoint(unsafe { Peripherals::steal() }).await;
                     });
                 })
                 .unwrap();
+
+                ::std::process::exit(exit_code);
             }
         }
     };
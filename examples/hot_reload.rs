@@ -0,0 +1,74 @@
+//! A program which is loaded by the simulator host, so it can be changed while it's running.
+//!
+//! ```sh
+//! cargo build --example hot_reload
+//! cargo run -p roboscope-host -F windowed -- target/debug/examples/libhot_reload.so
+//! ```
+//!
+//! Try changing the message below and building the example again: the simulator loads the new
+//! version and restarts the program.
+
+#![cfg(not(target_os = "vexos"))]
+
+use std::{
+    any::Any,
+    ffi::{CString, c_char, c_int},
+    panic, ptr,
+    sync::Mutex,
+};
+
+use vex_sdk::*;
+
+/// The message of the last panic, which the simulator shows on its error screen.
+static LAST_PANIC: Mutex<Option<CString>> = Mutex::new(None);
+
+#[unsafe(no_mangle)]
+pub extern "C" fn roboscope_main() -> c_int {
+    match panic::catch_unwind(entry) {
+        Ok(()) => 0,
+        Err(payload) => {
+            *LAST_PANIC.lock().unwrap() = CString::new(panic_message(&*payload)).ok();
+            1
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn roboscope_last_panic() -> *const c_char {
+    LAST_PANIC
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(ptr::null(), |message| message.as_ptr())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+fn entry() {
+    let message = c"Edit me and rebuild!";
+    let mut frame = 0;
+
+    unsafe {
+        loop {
+            vexDisplayForegroundColor(0x00_00_00);
+            vexDisplayRectFill(0, 0, 480, 240);
+
+            vexDisplayForegroundColor(0xFF_FF_FF);
+            vexDisplayPrintf(20, 40, 0, message.as_ptr());
+
+            let x = 40 + (frame % 400);
+            vexDisplayForegroundColor(0x00_80_FF);
+            vexDisplayCircleFill(x, 140, 20);
+            frame += 4;
+
+            vexDisplayRender(true, false);
+            vexTasksRun();
+        }
+    }
+}
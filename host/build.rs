@@ -0,0 +1,7 @@
+fn main() {
+    // Add the SDK functions to the dynamic symbol table so that loaded programs can link to them.
+    // Windows libraries can't link to functions in the executable that loads them this way.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        println!("cargo:rustc-link-arg-bins=-rdynamic");
+    }
+}
@@ -0,0 +1,225 @@
+//! Loading the program's library, and reloading it when it changes.
+
+use std::{
+    env,
+    ffi::{CStr, c_char, c_int},
+    fs, panic,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use libloading::Library;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, error, info, warn};
+use vex_sdk_desktop::PanicReport;
+
+/// The name of the function the program's library must export to be run.
+///
+/// The function has the signature `extern "C" fn() -> c_int`, and returns `0` when the program
+/// returns, or any other value if it panicked.
+pub const ENTRY_SYMBOL: &str = "roboscope_main";
+
+/// The name of a function the program's library can export to describe why it panicked.
+///
+/// The function has the signature `extern "C" fn() -> *const c_char`, and is called after the
+/// entrypoint returns a non-zero value. It returns the panic message as a NUL-terminated string,
+/// which must stay valid until the entrypoint is called again, or null if there isn't one.
+pub const LAST_PANIC_SYMBOL: &str = "roboscope_last_panic";
+
+/// How long the library must go without changing before it's reloaded, so that it isn't loaded
+/// while it's still being written.
+const RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+type EntryFn = unsafe extern "C" fn() -> c_int;
+type LastPanicFn = unsafe extern "C" fn() -> *const c_char;
+
+/// The functions exported by one version of the program's library.
+#[derive(Clone, Copy)]
+struct Exports {
+    entry: EntryFn,
+    last_panic: Option<LastPanicFn>,
+}
+
+/// The program's library, and the exports of the most recently loaded version of it.
+pub struct ProgramLibrary {
+    path: PathBuf,
+    name: String,
+    exports: Mutex<Exports>,
+    /// The number of versions of the library which have been loaded.
+    versions: AtomicUsize,
+}
+
+impl ProgramLibrary {
+    /// Load the library at the given path.
+    pub fn load(path: &Path) -> Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to find the program's library {}", path.display()))?;
+
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("Program");
+        let name = stem.strip_prefix("lib").unwrap_or(stem).to_string();
+
+        let exports = load_copy(&path, 0)?;
+        info!(path = %path.display(), "Loaded program");
+
+        Ok(Self {
+            path,
+            name,
+            exports: Mutex::new(exports),
+            versions: AtomicUsize::new(1),
+        })
+    }
+
+    /// The name of the program, taken from the library's file name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Load the current version of the library. The next run of the program will use it.
+    pub fn reload(&self) -> Result<()> {
+        let version = self.versions.fetch_add(1, Ordering::Relaxed);
+        let exports = load_copy(&self.path, version)?;
+        *self.exports.lock().unwrap() = exports;
+
+        info!(version, "Reloaded program");
+        Ok(())
+    }
+
+    /// Run the program's entrypoint on the current thread, panicking if the program panicked.
+    pub fn run(&self) {
+        let exports = *self.exports.lock().unwrap();
+
+        // SAFETY: the library is never unloaded, and the entrypoint is expected to have the
+        // signature documented on `ENTRY_SYMBOL`.
+        let status = unsafe { (exports.entry)() };
+        if status == 0 {
+            return;
+        }
+
+        // The program's panic can't unwind into the simulator, since the library has its own copy
+        // of the standard library. Its panic hook has already printed the details, so the message
+        // is shown on the error screen and the host unwinds without running its own hook.
+        let message = exports
+            .last_panic
+            .and_then(|last_panic| {
+                // SAFETY: the function is expected to have the signature documented on
+                // `LAST_PANIC_SYMBOL`, and the string stays valid until the program runs again.
+                let message = unsafe { last_panic() };
+                (!message.is_null())
+                    .then(|| unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| format!("The program panicked (status {status})"));
+
+        vex_sdk_desktop::report_panic(PanicReport {
+            message: message.clone(),
+            location: None,
+            backtrace: Vec::new(),
+        });
+        panic::resume_unwind(Box::new(message));
+    }
+
+    /// Returns `true` if the event might have changed the library's file.
+    fn is_changed_by(&self, event: &Event) -> bool {
+        matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == self.path.file_name())
+    }
+}
+
+/// Load a copy of the library and find its exports.
+///
+/// Opening the same path twice would give back the version which is already loaded, so each
+/// version is copied to its own file first.
+fn load_copy(path: &Path, version: usize) -> Result<Exports> {
+    let file_name = path.file_name().context("Library path has no file name")?;
+    let copy = env::temp_dir().join(format!(
+        "roboscope-{}-{version}-{}",
+        process::id(),
+        file_name.to_string_lossy(),
+    ));
+
+    fs::copy(path, &copy)
+        .with_context(|| format!("Failed to copy the program's library to {}", copy.display()))?;
+
+    // SAFETY: loading the library runs its initializers, which is only as safe as the program.
+    let library = unsafe { Library::new(&copy) };
+
+    // The loaded library stays mapped after its file is removed (except on Windows, where the
+    // file can't be removed until the simulator exits).
+    if let Err(error) = fs::remove_file(&copy) {
+        debug!(%error, path = %copy.display(), "Failed to remove copy of the program's library");
+    }
+
+    let library = library.with_context(|| format!("Failed to load {}", path.display()))?;
+
+    // Threads from earlier runs of the program may still be parked inside its code, so libraries
+    // are never unloaded.
+    let library: &'static Library = Box::leak(Box::new(library));
+
+    // SAFETY: the symbol is expected to have the signature documented on `ENTRY_SYMBOL`.
+    let entry = unsafe { library.get::<EntryFn>(ENTRY_SYMBOL.as_bytes()) }
+        .with_context(|| format!("The program's library doesn't export `{ENTRY_SYMBOL}`"))?;
+
+    // SAFETY: the symbol is expected to have the signature documented on `LAST_PANIC_SYMBOL`.
+    let last_panic = unsafe { library.get::<LastPanicFn>(LAST_PANIC_SYMBOL.as_bytes()) }.ok();
+
+    Ok(Exports {
+        entry: *entry,
+        last_panic: last_panic.map(|last_panic| *last_panic),
+    })
+}
+
+/// Reload the program and restart it whenever its library changes.
+///
+/// The returned watcher stops watching the library when it's dropped.
+pub fn watch(program: Arc<ProgramLibrary>) -> Result<RecommendedWatcher> {
+    let (events_tx, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events_tx)?;
+
+    // Build tools usually replace the library instead of writing to it, which some platforms
+    // don't report as a change to the old file, so its whole directory is watched.
+    let dir = program.path.parent().unwrap_or(Path::new("."));
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+    thread::Builder::new()
+        .name("Program Reloader".into())
+        .spawn(move || reload_on_change(&program, &events))?;
+
+    Ok(watcher)
+}
+
+fn reload_on_change(program: &ProgramLibrary, events: &Receiver<notify::Result<Event>>) {
+    while let Ok(event) = events.recv() {
+        match event {
+            Ok(event) if program.is_changed_by(&event) => {}
+            Ok(_) => continue,
+            Err(error) => {
+                warn!(%error, "Failed to watch the program's library");
+                continue;
+            }
+        }
+
+        // Wait for the build to finish writing the library.
+        while events.recv_timeout(RELOAD_DELAY).is_ok() {}
+
+        match program.reload() {
+            Ok(()) => vex_sdk_desktop::restart_program(),
+            Err(error) => error!(%error, "Failed to reload the program, keeping the old version"),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Runs a robot program built as a dynamic library in the simulator.
+//!
+//! The program is built as a `cdylib` which exports a [`library::ENTRY_SYMBOL`] function. It
+//! doesn't link to the simulator itself: the SDK functions it calls are left undefined, and are
+//! resolved from this executable when the library is loaded. When the library is rebuilt, the new
+//! version is loaded and the program restarts without restarting the simulator, so connected
+//! viewers and physics providers can stay running.
+
+use std::{path::PathBuf, process, sync::Arc};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+use vex_sdk_desktop::signature;
+
+use crate::library::ProgramLibrary;
+
+mod library;
+
+/// Runs a robot program built as a dynamic library in the VEX V5 simulator, and reloads it
+/// whenever it's rebuilt.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The program's library, e.g. `target/debug/libmy_robot.so`.
+    library: PathBuf,
+
+    /// Don't reload the program when the library changes.
+    #[arg(long)]
+    no_watch: bool,
+
+    /// Simulator settings, e.g. `-- --theme light`.
+    #[arg(last = true)]
+    settings: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+
+    let program = Arc::new(ProgramLibrary::load(&args.library)?);
+    let name = program.name().to_string();
+
+    if let Some(code_signature) = signature::read_from_file(&args.library)
+        .context("Failed to read the program's code signature")?
+    {
+        signature::set(code_signature);
+    }
+
+    // The watcher stops when it's dropped, so it's kept until the simulator exits.
+    let _watcher = if args.no_watch {
+        None
+    } else {
+        Some(library::watch(program.clone())?)
+    };
+
+    let exit_code = vex_sdk_desktop::run_simulator_as(&name, &args.settings, move || {
+        program.run();
+    })?;
+
+    process::exit(exit_code);
+}
@@ -1,7 +1,15 @@
-use roboscope_ipc::{Config, DeviceReadings, DistanceSnapshot, SimServices};
+use std::env;
+
+use roboscope_ipc::{DeviceReadings, DistanceSnapshot, IpcOptions, SimServices};
 
 fn main() {
-    let sim = SimServices::join(Some("Oscillator example"), &Config::default()).unwrap();
+    let config = IpcOptions::from_env().unwrap().config().unwrap();
+
+    // Use `--robot <ID>` to choose which simulator to connect to when several are running.
+    let robot = env::args().skip_while(|arg| arg != "--robot").nth(1);
+    let robot = SimServices::find_robot(robot.as_deref(), &config).unwrap();
+
+    let sim = SimServices::join(Some("Oscillator example"), &robot, &config).unwrap();
 
     let mut readings = DeviceReadings::default();
 
@@ -1,4 +1,21 @@
-use iceoryx2::{node::NodeCreationFailure, port::{LoanError, ReceiveError, SendError, publisher::PublisherCreateError, subscriber::SubscriberCreateError}, service::builder::publish_subscribe::PublishSubscribeOpenOrCreateError};
+use iceoryx2::{
+    node::NodeCreationFailure,
+    port::{
+        LoanError, ReceiveError, SendError,
+        listener::{ListenerCreateError, ListenerWaitError},
+        notifier::{NotifierCreateError, NotifierNotifyError},
+        publisher::PublisherCreateError,
+        subscriber::SubscriberCreateError,
+    },
+    service::{
+        ServiceListError,
+        builder::{
+            event::{EventOpenError, EventOpenOrCreateError},
+            publish_subscribe::{PublishSubscribeOpenError, PublishSubscribeOpenOrCreateError},
+        },
+        service_name::ServiceName,
+    },
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -23,7 +40,73 @@ pub enum RoboscopeIpcError {
 
     #[error("Failed to send a packet")]
     SendError(#[from] SendError),
+
+    #[error(
+        "The {service} service is being used by a process built against a different version of \
+        roboscope-ipc (expected schema {expected:016x})"
+    )]
+    SchemaMismatch { service: String, expected: u64 },
+
+    #[error("Failed to establish an event service")]
+    SetupEvents(#[from] EventOpenOrCreateError),
+
+    #[error("Failed to begin sending notifications")]
+    CreateNotifier(#[from] NotifierCreateError),
+
+    #[error("Failed to begin listening for notifications")]
+    CreateListener(#[from] ListenerCreateError),
+
+    #[error("Failed to send a notification")]
+    Notify(#[from] NotifierNotifyError),
+
+    #[error("Failed to wait for a notification")]
+    Wait(#[from] ListenerWaitError),
+
+    #[error("Failed to list the running simulators")]
+    ListServices(#[from] ServiceListError),
+
+    #[error("Invalid IPC {option} {value:?}")]
+    InvalidOption { option: &'static str, value: String },
+
+    #[error("Invalid robot ID {0:?} (IDs can't be empty or contain `/`)")]
+    InvalidRobotId(String),
+
+    #[error("Several simulators are running, choose one of these robots: {}", .0.join(", "))]
+    AmbiguousRobot(Vec<String>),
 }
 
+impl RoboscopeIpcError {
+    /// Convert an error opening a service, detecting services created with a different schema.
+    pub(crate) fn from_open(
+        service: &ServiceName,
+        error: PublishSubscribeOpenOrCreateError,
+    ) -> Self {
+        match error {
+            PublishSubscribeOpenOrCreateError::PublishSubscribeOpenError(
+                PublishSubscribeOpenError::IncompatibleAttributes
+                | PublishSubscribeOpenError::IncompatibleTypes,
+            ) => Self::schema_mismatch(service),
+            error => Self::SetupService(error),
+        }
+    }
+
+    /// Convert an error opening an event service, detecting services created with a different
+    /// schema.
+    pub(crate) fn from_open_events(service: &ServiceName, error: EventOpenOrCreateError) -> Self {
+        match error {
+            EventOpenOrCreateError::EventOpenError(EventOpenError::IncompatibleAttributes) => {
+                Self::schema_mismatch(service)
+            }
+            error => Self::SetupEvents(error),
+        }
+    }
+
+    fn schema_mismatch(service: &ServiceName) -> Self {
+        Self::SchemaMismatch {
+            service: service.as_str().to_string(),
+            expected: crate::SCHEMA_HASH,
+        }
+    }
+}
 
 pub type SimResult<T> = Result<T, RoboscopeIpcError>;
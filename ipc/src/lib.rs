@@ -4,11 +4,14 @@
 
 use std::fmt::Debug;
 use std::mem::MaybeUninit;
+use std::ops::ControlFlow;
 use std::sync::LazyLock;
 use std::time::Duration;
 
 use derive_more::{From, TryInto};
-use iceoryx2::{port::publisher::Publisher, prelude::*};
+use iceoryx2::prelude::*;
+use iceoryx2::port::listener::ListenerWaitError;
+use iceoryx2::service::Service as _;
 
 use crate::error::{RoboscopeIpcError, SimResult};
 
@@ -17,13 +20,32 @@ use ipc_threadsafe as ipc;
 
 pub type PubSubFactory<T> =
     iceoryx2::service::port_factory::publish_subscribe::PortFactory<ipc::Service, T, ()>;
+pub type Publisher<T> =
+    iceoryx2::port::publisher::Publisher<ipc::Service, T, ()>;
 pub type Subscriber<T> =
     iceoryx2::port::subscriber::Subscriber<ipc::Service, T, ()>;
 pub type Sample<T> =
     iceoryx2::sample::Sample<ipc::Service, T, ()>;
+pub type BytesPubSubFactory =
+    iceoryx2::service::port_factory::publish_subscribe::PortFactory<ipc::Service, [u8], ()>;
+pub type EventFactory =
+    iceoryx2::service::port_factory::event::PortFactory<ipc::Service>;
+pub type Notifier =
+    iceoryx2::port::notifier::Notifier<ipc::Service>;
+pub type Listener =
+    iceoryx2::port::listener::Listener<ipc::Service>;
 pub use iceoryx2::config::Config;
 
 pub mod error;
+pub mod options;
+pub mod schema;
+#[cfg(feature = "screenshot")]
+pub mod screenshot;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+
+pub use options::IpcOptions;
+pub use schema::{SCHEMA_HASH, SCHEMA_VERSION};
 
 pub const PHYSICS_UPDATE_PERIOD: Duration = Duration::from_millis(10);
 pub const SMART_DEVICES_COUNT: usize = 21;
@@ -34,9 +56,26 @@ pub const DISPLAY_WIDTH: u32 = 480;
 pub const DISPLAY_HEIGHT: u32 = 272;
 pub const DISPLAY_BUF_SIZE: usize = DISPLAY_WIDTH as usize * DISPLAY_HEIGHT as usize;
 
+/// The ID of the robot used when no other ID is given.
+pub const DEFAULT_ROBOT: &str = "default";
+
+/// The prefix of every service name, which is followed by the robot's ID.
+const SERVICE_PREFIX: &str = "vexide/roboscope";
+/// The service every running simulator creates, used to discover them.
+const PROGRAM_STATUS_SERVICE: &str = "program_status";
+const DISPLAY_FRAMES_SERVICE: &str = "display_frames";
+const DRAW_CALLS_SERVICE: &str = "draw_calls";
+const DEVICE_READINGS_SERVICE: &str = "device_readings";
+
+/// The initial size of the buffers used to publish draw calls. Larger batches cause the buffers
+/// to be reallocated.
+const DRAW_CALLS_INITIAL_LEN: usize = 64 * 1024;
+
+/// Readings published by a physics provider: one snapshot per smart port, and optionally the
+/// state of the battery.
 #[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default)]
 #[repr(C)]
-pub struct DeviceReadings(pub [DeviceSnapshot; SMART_DEVICES_COUNT]);
+pub struct DeviceReadings(pub [DeviceSnapshot; SMART_DEVICES_COUNT], pub BatteryReading);
 
 #[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default, From, TryInto)]
 #[try_into(owned, ref, ref_mut)]
@@ -46,6 +85,7 @@ pub enum DeviceSnapshot {
     Empty,
     Generic(GenericSnapshot),
     Distance(DistanceSnapshot),
+    Motor(MotorSnapshot),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default)]
@@ -76,6 +116,45 @@ impl Default for DistanceSnapshot {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default)]
+#[repr(C)]
+pub struct MotorSnapshot {
+    /// Position of the motor shaft, in degrees.
+    pub position: f64,
+    /// Velocity of the motor shaft, in RPM.
+    pub velocity: f64,
+    /// Current drawn by the motor, in milliamps.
+    pub current: i32,
+    /// Voltage applied to the motor, in millivolts.
+    pub voltage: i32,
+    /// Temperature of the motor, in degrees Celsius.
+    pub temperature: f64,
+}
+
+/// Where the simulator gets the state of the battery from.
+#[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default, From)]
+#[repr(C)]
+pub enum BatteryReading {
+    /// The simulator's own battery model is used, which drains with the current drawn by motors.
+    #[default]
+    Simulated,
+    /// The physics provider is simulating the battery itself.
+    Override(BatterySnapshot),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend)]
+#[repr(C)]
+pub struct BatterySnapshot {
+    /// Remaining capacity, in percent.
+    pub capacity: f64,
+    /// Battery voltage, in millivolts.
+    pub voltage: i32,
+    /// Total current drawn from the battery, in milliamps.
+    pub current: i32,
+    /// Temperature of the battery, in degrees Celsius.
+    pub temperature: f64,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, ZeroCopySend, Default)]
 #[repr(C)]
 pub struct RobotOutputs(pub [DeviceCommand; SMART_DEVICES_COUNT]);
@@ -91,17 +170,129 @@ pub enum DeviceCommand {
 #[debug("DisplayFrame")]
 #[repr(C)]
 pub struct DisplayFrame {
+    pub info: FrameInfo,
     pub buffer: [u32; DISPLAY_BUF_SIZE],
 }
 
+/// Metadata describing a published [`DisplayFrame`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend, Default)]
+#[repr(C)]
+pub struct FrameInfo {
+    /// The number of frames published by the simulator before this one.
+    pub sequence: u64,
+    /// The simulated time when the frame was rendered, measured in microseconds since the program
+    /// started.
+    pub timestamp_us: u64,
+    /// The region of the display which changed since the previous frame was published.
+    ///
+    /// This is empty if the frame is identical to the previous one. Consumers which might not
+    /// have seen the previous frame should use [`FrameInfo::changed_since`] instead.
+    pub dirty: FrameRect,
+    /// Whether the program header is drawn at the top of the display.
+    pub header_visible: bool,
+    /// Whether the user canvas is automatically rendered each frame, i.e. the program isn't
+    /// calling `vexDisplayRender` itself.
+    pub autorender: bool,
+}
+
+impl FrameInfo {
+    /// Get the region of the display which changed since the frame with the given sequence number.
+    ///
+    /// The entire display is considered changed if there isn't a previous frame or if any frames
+    /// were published in between.
+    pub fn changed_since(&self, previous_sequence: Option<u64>) -> FrameRect {
+        if previous_sequence.is_some_and(|previous| previous + 1 == self.sequence) {
+            self.dirty
+        } else {
+            FrameRect::FULL
+        }
+    }
+
+    /// Get the time when the frame was rendered, measured since the program started.
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_micros(self.timestamp_us)
+    }
+}
+
+/// A rectangular region of the display, measured in pixels from the upper-left corner.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend, Default)]
+#[repr(C)]
+pub struct FrameRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameRect {
+    /// A region covering the entire display.
+    pub const FULL: Self = Self {
+        x: 0,
+        y: 0,
+        width: DISPLAY_WIDTH,
+        height: DISPLAY_HEIGHT,
+    };
+
+    /// Returns `true` if the region doesn't contain any pixels.
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// The state of the simulated touchscreen, as controlled by a viewer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend, Default)]
+#[repr(C)]
+pub struct TouchInput {
+    /// The position being touched, measured in pixels from the upper-left corner of the display.
+    pub x: i32,
+    pub y: i32,
+    /// Whether the screen is being pressed.
+    pub pressed: bool,
+}
+
+/// Whether the program running in the simulator has stopped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend, Default)]
+#[repr(C)]
+pub struct ProgramStatus {
+    pub state: ProgramState,
+    /// The exit code the simulator will return with, once the program has stopped.
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend, Default)]
+#[repr(C)]
+pub enum ProgramState {
+    #[default]
+    Running,
+    Stopped,
+}
+
+/// A command sent from a viewer to control the program running in the simulator.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ZeroCopySend)]
+#[repr(C)]
+pub enum ControlCommand {
+    /// Run the program again from the start, without restarting the simulator.
+    Restart,
+}
+
+/// The connection to the services of one simulated robot.
+///
+/// Every service name is prefixed with the robot's ID (e.g. `vexide/roboscope/red/display_frames`),
+/// so several simulators can run on the same machine without seeing each other's data.
 #[derive(Debug)]
 pub struct SimServices {
     pub node: Node<ipc::Service>,
+    robot: String,
 }
 
 impl SimServices {
-    pub fn join(name: Option<&str>, config: &Config) -> SimResult<Self> {
-        let mut node = NodeBuilder::new().config(&Config::default());
+    /// Join the services of the robot with the given ID, creating them if they don't exist yet.
+    pub fn join(name: Option<&str>, robot: &str, config: &Config) -> SimResult<Self> {
+        if robot.is_empty() || robot.contains('/') || ServiceName::new(robot).is_err() {
+            return Err(RoboscopeIpcError::InvalidRobotId(robot.to_string()));
+        }
+
+        let mut node = NodeBuilder::new().config(config);
 
         if let Some(name) = name {
             let fmted_name = format!("roboscope.{name}");
@@ -110,33 +301,169 @@ impl SimServices {
 
         Ok(Self {
             node: node.create()?,
+            robot: robot.to_string(),
         })
     }
 
+    /// List the IDs of the robots which have a simulator running, in alphabetical order.
+    pub fn running_robots(config: &Config) -> SimResult<Vec<String>> {
+        let mut robots = Vec::new();
+
+        ipc::Service::list(config, |service| {
+            let name = service.static_details.name().as_str();
+            let robot = name
+                .strip_prefix(SERVICE_PREFIX)
+                .and_then(|name| name.strip_prefix('/'))
+                .and_then(|name| name.strip_suffix(PROGRAM_STATUS_SERVICE))
+                .and_then(|name| name.strip_suffix('/'));
+
+            if let Some(robot) = robot {
+                robots.push(robot.to_string());
+            }
+
+            CallbackProgression::Continue
+        })?;
+
+        robots.sort();
+        robots.dedup();
+        Ok(robots)
+    }
+
+    /// Choose which robot to connect to.
+    ///
+    /// If no ID is given, this is the only robot with a simulator running, or [`DEFAULT_ROBOT`] if
+    /// there aren't any. It's an error to not give an ID while several simulators are running.
+    pub fn find_robot(robot: Option<&str>, config: &Config) -> SimResult<String> {
+        if let Some(robot) = robot {
+            return Ok(robot.to_string());
+        }
+
+        let mut robots = Self::running_robots(config)?;
+        match robots.len() {
+            0 => Ok(DEFAULT_ROBOT.to_string()),
+            1 => Ok(robots.remove(0)),
+            _ => Err(RoboscopeIpcError::AmbiguousRobot(robots)),
+        }
+    }
+
+    /// The ID of the robot these services belong to.
+    pub fn robot(&self) -> &str {
+        &self.robot
+    }
+
+    fn service_name(&self, service: &str) -> ServiceName {
+        let name = format!("{SERVICE_PREFIX}/{}/{service}", self.robot);
+        ServiceName::new(&name).expect("service name valid")
+    }
+
     fn pub_sub<T: Debug + ZeroCopySend>(
         &self,
         name: &str,
     ) -> SimResult<PubSubFactory<T>> {
-        let name = ServiceName::new(name).unwrap();
+        let name = self.service_name(name);
         let service = self
             .node
             .service_builder(&name)
             .publish_subscribe::<T>()
-            .open_or_create()?;
+            .open_or_create_with_attributes(&schema::attributes())
+            .map_err(|error| RoboscopeIpcError::from_open(&name, error))?;
+
+        Ok(service)
+    }
+
+    /// The event service paired with a publish-subscribe service. Publishers notify it after
+    /// sending data, so subscribers can wake up straight away instead of polling.
+    fn events(&self, name: &str) -> SimResult<EventFactory> {
+        let name = self.service_name(name);
+        let service = self
+            .node
+            .service_builder(&name)
+            .event()
+            .open_or_create_with_attributes(&schema::attributes())
+            .map_err(|error| RoboscopeIpcError::from_open_events(&name, error))?;
 
         Ok(service)
     }
 
+    /// Wait until the listener is notified or the timeout passes, whichever comes first.
+    ///
+    /// Like [`Node::wait`], this returns `false` if the process was asked to stop (e.g. with
+    /// Ctrl+C). The timeout is a fallback for publishers which don't send notifications.
+    pub fn wait_for_data(&self, listener: &Listener, timeout: Duration) -> SimResult<bool> {
+        match listener.timed_wait_all(|_| {}, timeout) {
+            Ok(()) => {}
+            Err(ListenerWaitError::InterruptSignal) => return Ok(false),
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(self.node.wait(Duration::ZERO).is_ok())
+    }
+
     pub fn display_frames(&self) -> SimResult<PubSubFactory<DisplayFrame>> {
-        self.pub_sub("vexide/roboscope/display_frames")
+        self.pub_sub(DISPLAY_FRAMES_SERVICE)
+    }
+
+    /// Notified whenever a display frame is published.
+    pub fn display_frame_events(&self) -> SimResult<EventFactory> {
+        self.events(DISPLAY_FRAMES_SERVICE)
+    }
+
+    /// Batches of draw calls made by the program, encoded as JSON Lines.
+    pub fn draw_calls(&self) -> SimResult<BytesPubSubFactory> {
+        let name = self.service_name(DRAW_CALLS_SERVICE);
+        let service = self
+            .node
+            .service_builder(&name)
+            .publish_subscribe::<[u8]>()
+            .open_or_create_with_attributes(&schema::attributes())
+            .map_err(|error| RoboscopeIpcError::from_open(&name, error))?;
+
+        Ok(service)
+    }
+
+    /// Notified whenever a batch of draw calls is published.
+    pub fn draw_call_events(&self) -> SimResult<EventFactory> {
+        self.events(DRAW_CALLS_SERVICE)
+    }
+
+    /// The status of the program running in the simulator.
+    ///
+    /// The most recent status is kept, so subscribers receive it as soon as they connect.
+    pub fn program_status(&self) -> SimResult<PubSubFactory<ProgramStatus>> {
+        let name = self.service_name(PROGRAM_STATUS_SERVICE);
+        let service = self
+            .node
+            .service_builder(&name)
+            .publish_subscribe::<ProgramStatus>()
+            .history_size(1)
+            .subscriber_max_buffer_size(1)
+            .open_or_create_with_attributes(&schema::attributes())
+            .map_err(|error| RoboscopeIpcError::from_open(&name, error))?;
+
+        Ok(service)
+    }
+
+    /// Touchscreen input sent from a viewer to the simulator.
+    pub fn touch_input(&self) -> SimResult<PubSubFactory<TouchInput>> {
+        self.pub_sub("touch_input")
+    }
+
+    /// Commands sent from a viewer to control the program.
+    pub fn control(&self) -> SimResult<PubSubFactory<ControlCommand>> {
+        self.pub_sub("control")
     }
 
     pub fn device_cmds(&self) -> SimResult<PubSubFactory<RobotOutputs>> {
-        self.pub_sub("vexide/roboscope/device_cmds")
+        self.pub_sub("device_cmds")
     }
 
     pub fn device_readings(&self) -> SimResult<PubSubFactory<DeviceReadings>> {
-        self.pub_sub("vexide/roboscope/device_readings")
+        self.pub_sub(DEVICE_READINGS_SERVICE)
+    }
+
+    /// Notified whenever the physics provider publishes device readings.
+    pub fn device_reading_events(&self) -> SimResult<EventFactory> {
+        self.events(DEVICE_READINGS_SERVICE)
     }
 
     pub fn publish_device_readings(
@@ -145,6 +472,7 @@ impl SimServices {
     ) -> SimResult<()> {
         let robot_subscriber = self.device_cmds()?.subscriber_builder().create()?;
         let captures = self.device_readings()?.publisher_builder().create()?;
+        let notifier = self.device_reading_events()?.notifier_builder().create()?;
 
         while self.node.wait(PHYSICS_UPDATE_PERIOD).is_ok() {
             let robot_outputs = robot_subscriber.receive()?;
@@ -155,6 +483,7 @@ impl SimServices {
                 .write_payload(physics_sim(physics_inputs));
 
             physics_outputs.send()?;
+            notifier.notify()?;
         }
 
         Ok(())
@@ -162,23 +491,82 @@ impl SimServices {
 
     /// Publish a stream of display frames to the simulator at 60Hz.
     ///
+    /// Publishing stops after the renderer returns [`ControlFlow::Break`], once the frame it
+    /// rendered has been sent.
+    ///
     /// # Safety
     ///
     /// The renderer callback is responsible for initializing the frame passed as its argument.
     pub unsafe fn publish_display(
         &self,
-        mut renderer: impl FnMut(&mut MaybeUninit<DisplayFrame>),
+        mut renderer: impl FnMut(&mut MaybeUninit<DisplayFrame>) -> ControlFlow<()>,
     ) -> SimResult<()> {
         let frames = self.display_frames()?.publisher_builder().create()?;
+        let notifier = self.display_frame_events()?.notifier_builder().create()?;
 
         while self.node.wait(*DISPLAY_UPDATE_PERIOD).is_ok() {
             let mut next_frame = frames.loan_uninit()?;
 
-            renderer(next_frame.payload_mut());
+            let flow = renderer(next_frame.payload_mut());
 
             // SAFETY: init'd by renderer
             let sample = unsafe { next_frame.assume_init() };
             sample.send()?;
+            notifier.notify()?;
+
+            if flow.is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Publish batches of draw calls at 60Hz.
+    ///
+    /// The callback is given an empty buffer to write the next batch of JSON Lines into. Nothing
+    /// is published if it leaves the buffer empty. Publishing stops after the callback returns
+    /// [`ControlFlow::Break`], once its batch has been sent.
+    pub fn publish_draw_calls(
+        &self,
+        mut next_batch: impl FnMut(&mut Vec<u8>) -> ControlFlow<()>,
+    ) -> SimResult<()> {
+        let publisher = self
+            .draw_calls()?
+            .publisher_builder()
+            .initial_max_slice_len(DRAW_CALLS_INITIAL_LEN)
+            .allocation_strategy(AllocationStrategy::PowerOfTwo)
+            .create()?;
+        let notifier = self.draw_call_events()?.notifier_builder().create()?;
+        let mut batch = Vec::new();
+
+        while self.node.wait(*DISPLAY_UPDATE_PERIOD).is_ok() {
+            batch.clear();
+            let flow = next_batch(&mut batch);
+
+            if !batch.is_empty() {
+                let sample = publisher.loan_slice_uninit(batch.len())?;
+                sample.write_from_slice(&batch).send()?;
+                notifier.notify()?;
+            }
+
+            if flow.is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receive batches of draw calls published by the simulator, encoded as JSON Lines.
+    pub fn stream_draw_calls(&self, mut cb: impl FnMut(&[u8])) -> SimResult<()> {
+        let batches = self.draw_calls()?.subscriber_builder().create()?;
+        let listener = self.draw_call_events()?.listener_builder().create()?;
+
+        while self.wait_for_data(&listener, *DISPLAY_UPDATE_PERIOD)? {
+            while let Some(batch) = batches.receive()? {
+                cb(batch.payload());
+            }
         }
 
         Ok(())
@@ -186,9 +574,10 @@ impl SimServices {
 
     pub fn stream_display(&self, mut cb: impl FnMut(&DisplayFrame)) -> SimResult<()> {
         let frames = self.display_frames()?.subscriber_builder().create()?;
+        let listener = self.display_frame_events()?.listener_builder().create()?;
 
-        while self.node.wait(*DISPLAY_UPDATE_PERIOD).is_ok() {
-            if let Some(next_frame) = frames.receive()? {
+        while self.wait_for_data(&listener, *DISPLAY_UPDATE_PERIOD)? {
+            while let Some(next_frame) = frames.receive()? {
                 cb(&next_frame);
             }
         }
@@ -0,0 +1,101 @@
+//! Roboscope-level options for the underlying iceoryx2 config.
+//!
+//! Every Roboscope process reads the same `ROBOSCOPE_IPC_*` environment variables, so a CI job
+//! can isolate its simulator and viewers from other runs on the same machine by setting them once:
+//!
+//! | Variable                      | Option                                              |
+//! | ----------------------------- | --------------------------------------------------- |
+//! | `ROBOSCOPE_IPC_ROOT`          | Directory iceoryx2 keeps its shared files in        |
+//! | `ROBOSCOPE_IPC_DOMAIN`        | Only see processes in the same domain               |
+//! | `ROBOSCOPE_IPC_BUFFER_SIZE`   | Samples each subscriber can hold before it's full   |
+//! | `ROBOSCOPE_IPC_HISTORY_SIZE`  | Samples new subscribers receive when they connect   |
+//! | `ROBOSCOPE_IPC_MAX_NODES`     | Processes which can use a service at the same time  |
+
+use std::{env, path::PathBuf, str::FromStr};
+
+use iceoryx2_bb_container::semantic_string::SemanticString;
+use iceoryx2_bb_system_types::{file_name::FileName, path::Path};
+
+use crate::{
+    Config,
+    error::{RoboscopeIpcError, SimResult},
+};
+
+/// The common iceoryx2 settings. Options which aren't set use iceoryx2's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpcOptions {
+    /// The directory iceoryx2 keeps its shared files in.
+    pub root_path: Option<PathBuf>,
+    /// Processes can only see the services of processes in the same domain.
+    pub domain: Option<String>,
+    /// The number of samples each subscriber can hold before it's full.
+    pub subscriber_buffer_size: Option<usize>,
+    /// The number of recent samples new subscribers receive when they connect.
+    pub history_size: Option<usize>,
+    /// The maximum number of processes which can use a service at the same time.
+    pub max_nodes: Option<usize>,
+}
+
+impl IpcOptions {
+    /// Read the options from `ROBOSCOPE_IPC_*` environment variables.
+    pub fn from_env() -> SimResult<Self> {
+        Ok(Self {
+            root_path: env::var_os("ROBOSCOPE_IPC_ROOT").map(PathBuf::from),
+            domain: env::var("ROBOSCOPE_IPC_DOMAIN").ok(),
+            subscriber_buffer_size: parse_env("ROBOSCOPE_IPC_BUFFER_SIZE")?,
+            history_size: parse_env("ROBOSCOPE_IPC_HISTORY_SIZE")?,
+            max_nodes: parse_env("ROBOSCOPE_IPC_MAX_NODES")?,
+        })
+    }
+
+    /// Build the iceoryx2 config to pass to [`SimServices::join`](crate::SimServices::join).
+    pub fn config(&self) -> SimResult<Config> {
+        let mut config = Config::default();
+
+        if let Some(root_path) = &self.root_path {
+            let path = Path::new(root_path.as_os_str().as_encoded_bytes()).map_err(|_| {
+                RoboscopeIpcError::InvalidOption {
+                    option: "root path",
+                    value: root_path.display().to_string(),
+                }
+            })?;
+            config.global.set_root_path(&path);
+        }
+
+        if let Some(domain) = &self.domain {
+            config.global.prefix = FileName::new(domain.as_bytes()).map_err(|_| {
+                RoboscopeIpcError::InvalidOption {
+                    option: "domain",
+                    value: domain.clone(),
+                }
+            })?;
+        }
+
+        let pub_sub = &mut config.defaults.publish_subscribe;
+        if let Some(size) = self.subscriber_buffer_size {
+            pub_sub.subscriber_max_buffer_size = size;
+        }
+        if let Some(size) = self.history_size {
+            pub_sub.publisher_history_size = size;
+        }
+        if let Some(max_nodes) = self.max_nodes {
+            pub_sub.max_nodes = max_nodes;
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_env<T: FromStr>(name: &'static str) -> SimResult<Option<T>> {
+    let Ok(value) = env::var(name) else {
+        return Ok(None);
+    };
+
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| RoboscopeIpcError::InvalidOption {
+            option: name,
+            value,
+        })
+}
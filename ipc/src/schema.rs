@@ -0,0 +1,182 @@
+//! Detecting peers built against a different version of this crate.
+//!
+//! The payloads of most services are `#[repr(C)]` types shared in place between processes, so a
+//! simulator and a physics provider built against different versions of `roboscope-ipc` would
+//! silently read each other's data as garbage. Every service is created with a
+//! [`SCHEMA_ATTRIBUTE`] holding [`SCHEMA_HASH`], and opening a service with a different hash fails
+//! with [`RoboscopeIpcError::SchemaMismatch`](crate::error::RoboscopeIpcError::SchemaMismatch).
+//!
+//! The hash covers the size and alignment of every shared type and the offsets of their fields,
+//! but not the meaning of the data, so [`SCHEMA_VERSION`] must be bumped whenever a shared type
+//! changes in a way that keeps its layout (e.g. reordering enum variants or changing units). The
+//! layouts are also pinned by a test, which fails as a reminder to bump the version.
+
+use std::{alloc::Layout, mem::offset_of};
+
+use iceoryx2::service::attribute::AttributeVerifier;
+
+use crate::{
+    BatteryReading, BatterySnapshot, ControlCommand, DeviceCommand, DeviceReadings,
+    DeviceSnapshot, DisplayFrame, DistanceSnapshot, FrameInfo, FrameRect, GenericSnapshot,
+    MotorSnapshot, ProgramState, ProgramStatus, RobotOutputs, TouchInput,
+};
+
+/// The name of the service attribute holding the schema hash.
+pub const SCHEMA_ATTRIBUTE: &str = "roboscope.schema";
+
+/// The version of the data sent between processes, which must be bumped whenever it changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The layouts of the types sent between processes, including the types nested inside them.
+const LAYOUTS: [Layout; 16] = [
+    Layout::new::<DeviceReadings>(),
+    Layout::new::<DeviceSnapshot>(),
+    Layout::new::<GenericSnapshot>(),
+    Layout::new::<DistanceSnapshot>(),
+    Layout::new::<MotorSnapshot>(),
+    Layout::new::<BatteryReading>(),
+    Layout::new::<BatterySnapshot>(),
+    Layout::new::<RobotOutputs>(),
+    Layout::new::<DeviceCommand>(),
+    Layout::new::<DisplayFrame>(),
+    Layout::new::<FrameInfo>(),
+    Layout::new::<FrameRect>(),
+    Layout::new::<TouchInput>(),
+    Layout::new::<ProgramStatus>(),
+    Layout::new::<ProgramState>(),
+    Layout::new::<ControlCommand>(),
+];
+
+/// The offsets of the fields of the structs sent between processes, in declaration order.
+const FIELD_OFFSETS: [usize; 29] = [
+    offset_of!(DeviceReadings, 0),
+    offset_of!(DeviceReadings, 1),
+    offset_of!(GenericSnapshot, value),
+    offset_of!(DistanceSnapshot, distance),
+    offset_of!(DistanceSnapshot, confidence),
+    offset_of!(DistanceSnapshot, status),
+    offset_of!(DistanceSnapshot, object_size),
+    offset_of!(DistanceSnapshot, object_velocity),
+    offset_of!(MotorSnapshot, position),
+    offset_of!(MotorSnapshot, velocity),
+    offset_of!(MotorSnapshot, current),
+    offset_of!(MotorSnapshot, voltage),
+    offset_of!(MotorSnapshot, temperature),
+    offset_of!(BatterySnapshot, capacity),
+    offset_of!(BatterySnapshot, voltage),
+    offset_of!(BatterySnapshot, current),
+    offset_of!(BatterySnapshot, temperature),
+    offset_of!(DisplayFrame, info),
+    offset_of!(DisplayFrame, buffer),
+    offset_of!(FrameInfo, sequence),
+    offset_of!(FrameInfo, timestamp_us),
+    offset_of!(FrameInfo, dirty),
+    offset_of!(FrameInfo, header_visible),
+    offset_of!(FrameInfo, autorender),
+    offset_of!(TouchInput, x),
+    offset_of!(TouchInput, y),
+    offset_of!(TouchInput, pressed),
+    offset_of!(ProgramStatus, state),
+    offset_of!(ProgramStatus, exit_code),
+];
+
+/// A hash of this crate's version, [`SCHEMA_VERSION`] and the layouts of the types sent between
+/// processes.
+pub const SCHEMA_HASH: u64 = {
+    let mut hash = Fnv1a::new()
+        .write(env!("CARGO_PKG_VERSION").as_bytes())
+        .write(&SCHEMA_VERSION.to_le_bytes());
+
+    let mut i = 0;
+    while i < LAYOUTS.len() {
+        hash = hash
+            .write(&LAYOUTS[i].size().to_le_bytes())
+            .write(&LAYOUTS[i].align().to_le_bytes());
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < FIELD_OFFSETS.len() {
+        hash = hash.write(&FIELD_OFFSETS[i].to_le_bytes());
+        i += 1;
+    }
+
+    hash.finish()
+};
+
+/// The attributes services are created with, and which existing services must have.
+pub(crate) fn attributes() -> AttributeVerifier {
+    let hash = format!("{SCHEMA_HASH:016x}");
+
+    AttributeVerifier::new()
+        .require(
+            &SCHEMA_ATTRIBUTE.try_into().expect("attribute key valid"),
+            &hash.as_str().try_into().expect("attribute value valid"),
+        )
+        .expect("schema attribute valid")
+}
+
+/// The 64-bit FNV-1a hash, which can be computed at compile time.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    const fn write(mut self, bytes: &[u8]) -> Self {
+        let mut i = 0;
+        while i < bytes.len() {
+            self.0 ^= bytes[i] as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+            i += 1;
+        }
+        self
+    }
+
+    const fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// If this fails, a shared type changed: update the expected layouts and bump
+    /// [`SCHEMA_VERSION`].
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn layouts_are_pinned() {
+        let layouts = LAYOUTS.map(|layout| (layout.size(), layout.align()));
+        assert_eq!(
+            layouts,
+            [
+                (872, 8),
+                (40, 8),
+                (4, 4),
+                (24, 8),
+                (32, 8),
+                (32, 8),
+                (24, 8),
+                (84, 4),
+                (4, 4),
+                (522_280, 8),
+                (40, 8),
+                (16, 4),
+                (12, 4),
+                (8, 4),
+                (4, 4),
+                (4, 4),
+            ]
+        );
+
+        assert_eq!(
+            FIELD_OFFSETS,
+            [
+                0, 840, 0, 0, 4, 8, 12, 16, 0, 8, 16, 20, 24, 0, 8, 12, 16, 0, 40, 0, 8, 16, 32,
+                33, 0, 4, 8, 0, 4,
+            ]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Saving display frames as PNG images, shared by the simulator and the viewer.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use fast_image_resize::{
+    ResizeAlg, ResizeError, ResizeOptions, Resizer,
+    images::{TypedImage, TypedImageRef},
+    pixels::U8x4,
+};
+use image::{ImageError, RgbImage};
+use thiserror::Error;
+
+use crate::{DISPLAY_BUF_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+#[derive(Debug, Error)]
+pub enum ScreenshotError {
+    #[error("Failed to scale the display")]
+    Resize(#[from] ResizeError),
+
+    #[error("Failed to save the image")]
+    Save(#[from] ImageError),
+}
+
+/// Save a frame's pixels as a PNG image at the display's native resolution.
+pub fn save_png(
+    buffer: &[u32; DISPLAY_BUF_SIZE],
+    path: impl AsRef<Path>,
+) -> Result<(), ScreenshotError> {
+    let pixels = buffer.iter().flat_map(|&pixel| rgb(pixel)).collect();
+
+    let img = RgbImage::from_raw(DISPLAY_WIDTH, DISPLAY_HEIGHT, pixels)
+        .expect("display dimensions are always valid");
+    img.save(path.as_ref())?;
+    Ok(())
+}
+
+/// Save a frame's pixels as a PNG image scaled to the given size.
+///
+/// Pixels are scaled with nearest-neighbor sampling, so the image looks the same as the display
+/// does in a window of that size.
+pub fn save_scaled_png(
+    buffer: &[u32; DISPLAY_BUF_SIZE],
+    width: u32,
+    height: u32,
+    path: impl AsRef<Path>,
+) -> Result<(), ScreenshotError> {
+    let buffer_pixels: &[U8x4] = bytemuck::must_cast_slice(buffer);
+    let frame_image = TypedImageRef::new(DISPLAY_WIDTH, DISPLAY_HEIGHT, buffer_pixels)
+        .expect("display dimensions are always valid");
+    let mut scaled = TypedImage::<U8x4>::new(width, height);
+
+    Resizer::new().resize_typed::<U8x4>(
+        &frame_image,
+        &mut scaled,
+        &ResizeOptions::new()
+            .resize_alg(ResizeAlg::Nearest)
+            .use_alpha(false),
+    )?;
+
+    let pixels = scaled
+        .pixels()
+        .iter()
+        .flat_map(|pixel| rgb(u32::from_ne_bytes(pixel.0)))
+        .collect();
+
+    let img = RgbImage::from_raw(width, height, pixels)
+        .expect("scaled image has the requested dimensions");
+    img.save(path.as_ref())?;
+    Ok(())
+}
+
+/// Get a file name (without an extension) named after the current time, like
+/// `roboscope-screenshot-1767225600123`.
+pub fn timestamped_name(kind: &str) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    format!("roboscope-{kind}-{millis}")
+}
+
+fn rgb(pixel: u32) -> [u8; 3] {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    [r, g, b]
+}
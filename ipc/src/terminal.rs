@@ -0,0 +1,264 @@
+//! Drawing the display in a terminal, shared by the simulator's terminal frontend and the viewer.
+//!
+//! Each character cell shows two pixels using the upper half block character (`▀`), with the
+//! foreground color as the top pixel and the background color as the bottom one. The display is
+//! downscaled to fit the terminal, which needs to support truecolor escape codes. Clicking and
+//! dragging with the left mouse button touches the screen, `r` asks to restart the program, and
+//! `q`, `Esc` or `Ctrl+C` asks to close the display.
+
+use std::{
+    io::{self, BufWriter, Stdout, Write},
+    time::Duration,
+};
+
+use crossterm::{
+    QueueableCommand, cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEventKind,
+    },
+    style::{Color, Print, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use fast_image_resize::{
+    FilterType, ResizeAlg, ResizeOptions, Resizer,
+    images::{TypedImage, TypedImageRef},
+    pixels::U8x4,
+};
+use tracing::debug;
+
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Input received from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalInput {
+    /// The user asked to close the display.
+    Quit,
+    /// The user asked to restart the program.
+    Restart,
+    /// The mouse was pressed, dragged or released over the display.
+    Touch { x: i32, y: i32, pressed: bool },
+}
+
+/// Where the display is drawn in the terminal, measured in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    left: u16,
+    top: u16,
+    cols: u16,
+    rows: u16,
+}
+
+impl Layout {
+    /// Fit the display inside a terminal of the given size, keeping its aspect ratio.
+    fn fit(term_cols: u16, term_rows: u16) -> Self {
+        let max_width = u32::from(term_cols).max(1);
+        let max_height = u32::from(term_rows).max(1) * 2;
+
+        let (width, height) = if max_width * DISPLAY_HEIGHT <= max_height * DISPLAY_WIDTH {
+            (max_width, max_width * DISPLAY_HEIGHT / DISPLAY_WIDTH)
+        } else {
+            (max_height * DISPLAY_WIDTH / DISPLAY_HEIGHT, max_height)
+        };
+
+        // Never scale the display up, since that only makes it blurrier.
+        let cols = width.clamp(1, DISPLAY_WIDTH) as u16;
+        let rows = height.clamp(2, DISPLAY_HEIGHT).div_ceil(2) as u16;
+
+        Self {
+            left: term_cols.saturating_sub(cols) / 2,
+            top: term_rows.saturating_sub(rows) / 2,
+            cols,
+            rows,
+        }
+    }
+
+    /// Convert the position of a character cell to the display pixel in the middle of it, or
+    /// `None` if the cell is outside of the display.
+    fn to_display(self, col: u16, row: u16) -> Option<(i32, i32)> {
+        let col = col.checked_sub(self.left).filter(|&col| col < self.cols)?;
+        let row = row.checked_sub(self.top).filter(|&row| row < self.rows)?;
+
+        Some((
+            ((2 * u32::from(col) + 1) * DISPLAY_WIDTH / (2 * u32::from(self.cols))) as i32,
+            ((2 * u32::from(row) + 1) * DISPLAY_HEIGHT / (2 * u32::from(self.rows))) as i32,
+        ))
+    }
+}
+
+/// A terminal in raw mode which the display is drawn on.
+///
+/// The terminal is restored to its original state when this is dropped.
+pub struct TerminalScreen {
+    out: BufWriter<Stdout>,
+    layout: Layout,
+    resizer: Resizer,
+    /// The colors of each character cell the last time it was drawn, as `(top, bottom)` pairs.
+    /// Only cells which changed are written to the terminal.
+    cells: Vec<(u32, u32)>,
+    needs_redraw: bool,
+    /// Where the last touch happened, so that releasing the mouse outside of the display still
+    /// releases the touch.
+    last_touch: Option<(i32, i32)>,
+}
+
+impl TerminalScreen {
+    /// Switch the terminal to raw mode and an alternate screen, and start capturing the mouse.
+    pub fn enter() -> io::Result<Self> {
+        let (cols, rows) = terminal::size()?;
+        debug!(cols, rows, "Entering terminal display mode");
+
+        terminal::enable_raw_mode()?;
+        let mut out = BufWriter::new(io::stdout());
+        out.queue(EnterAlternateScreen)?
+            .queue(EnableMouseCapture)?
+            .queue(cursor::Hide)?
+            .flush()?;
+
+        Ok(Self {
+            out,
+            layout: Layout::fit(cols, rows),
+            resizer: Resizer::new(),
+            cells: Vec::new(),
+            needs_redraw: true,
+            last_touch: None,
+        })
+    }
+
+    /// Returns `true` if the terminal needs to be redrawn even if the display hasn't changed,
+    /// e.g. because it was resized.
+    pub fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+
+    /// Handle any pending terminal events.
+    pub fn poll_input(&mut self) -> io::Result<Vec<TerminalInput>> {
+        let mut inputs = Vec::new();
+
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    let is_quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                        || (key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL));
+
+                    if is_quit {
+                        inputs.push(TerminalInput::Quit);
+                    } else if key.code == KeyCode::Char('r') {
+                        inputs.push(TerminalInput::Restart);
+                    }
+                }
+                Event::Mouse(mouse) => {
+                    let pressed = match mouse.kind {
+                        MouseEventKind::Down(MouseButton::Left)
+                        | MouseEventKind::Drag(MouseButton::Left) => true,
+                        MouseEventKind::Up(MouseButton::Left) => false,
+                        _ => continue,
+                    };
+
+                    let point = self.layout.to_display(mouse.column, mouse.row);
+
+                    // Presses outside of the display are ignored, but releases always count.
+                    let Some((x, y)) = point.or(self.last_touch.filter(|_| !pressed)) else {
+                        continue;
+                    };
+
+                    self.last_touch = Some((x, y));
+                    inputs.push(TerminalInput::Touch { x, y, pressed });
+                }
+                Event::Resize(cols, rows) => {
+                    self.layout = Layout::fit(cols, rows);
+                    self.needs_redraw = true;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    /// Draw the given display buffer in the terminal.
+    pub fn draw(&mut self, buffer: &[u32]) -> io::Result<()> {
+        let layout = self.layout;
+        let width = u32::from(layout.cols);
+        let height = u32::from(layout.rows) * 2;
+
+        if self.needs_redraw {
+            self.needs_redraw = false;
+            self.cells.clear();
+            self.out.queue(terminal::Clear(terminal::ClearType::All))?;
+        }
+
+        // Average the pixels covered by each half-cell, which looks much better than nearest
+        // neighbor sampling when the display is shrunk.
+        let buffer_pixels: &[U8x4] = bytemuck::must_cast_slice(buffer);
+        let screen = TypedImageRef::new(DISPLAY_WIDTH, DISPLAY_HEIGHT, buffer_pixels).unwrap();
+        let mut scaled = TypedImage::<U8x4>::new(width, height);
+        self.resizer
+            .resize_typed::<U8x4>(
+                &screen,
+                &mut scaled,
+                &ResizeOptions::new()
+                    .resize_alg(ResizeAlg::Convolution(FilterType::Box))
+                    .use_alpha(false),
+            )
+            .unwrap();
+
+        let pixels = scaled.pixels();
+        let pixel = |x: u32, y: u32| u32::from_ne_bytes(pixels[(y * width + x) as usize].0);
+
+        self.cells.resize(layout.cols as usize * layout.rows as usize, (u32::MAX, u32::MAX));
+
+        let mut cursor = None;
+        let mut colors = None;
+
+        for row in 0..layout.rows {
+            for col in 0..layout.cols {
+                let (x, y) = (u32::from(col), u32::from(row) * 2);
+                let cell = (pixel(x, y), pixel(x, y + 1));
+
+                let cached = &mut self.cells[row as usize * layout.cols as usize + col as usize];
+                if *cached == cell {
+                    continue;
+                }
+                *cached = cell;
+
+                let position = (layout.left + col, layout.top + row);
+                if cursor != Some(position) {
+                    self.out.queue(cursor::MoveTo(position.0, position.1))?;
+                }
+
+                if colors != Some(cell) {
+                    self.out
+                        .queue(SetForegroundColor(rgb(cell.0)))?
+                        .queue(SetBackgroundColor(rgb(cell.1)))?;
+                    colors = Some(cell);
+                }
+
+                self.out.queue(Print('▀'))?;
+                cursor = Some((position.0 + 1, position.1));
+            }
+        }
+
+        self.out.flush()
+    }
+}
+
+impl Drop for TerminalScreen {
+    fn drop(&mut self) {
+        _ = self
+            .out
+            .queue(SetForegroundColor(Color::Reset))
+            .and_then(|out| out.queue(SetBackgroundColor(Color::Reset)))
+            .and_then(|out| out.queue(cursor::Show))
+            .and_then(|out| out.queue(DisableMouseCapture))
+            .and_then(|out| out.queue(LeaveAlternateScreen))
+            .and_then(|out| out.flush());
+        _ = terminal::disable_raw_mode();
+    }
+}
+
+fn rgb(pixel: u32) -> Color {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    Color::Rgb { r, g, b }
+}
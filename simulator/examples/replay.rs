@@ -0,0 +1,46 @@
+//! Replays a draw-call journal saved as JSON Lines, writing the canvas to PNG files.
+//!
+//! ```sh
+//! cargo run -p vex-sdk-desktop --example replay -- calls.jsonl [output-dir] [--final]
+//! ```
+//!
+//! By default, an image is saved after every call that drew something. With `--final`, only the
+//! finished canvas is saved.
+
+use std::{env, path::PathBuf};
+
+use anyhow::bail;
+use vex_sdk_desktop::journal::Replay;
+
+fn main() -> anyhow::Result<()> {
+    let mut only_final = false;
+    let mut paths = Vec::new();
+
+    for arg in env::args().skip(1) {
+        if arg == "--final" {
+            only_final = true;
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+
+    let (journal, output_dir) = match paths.as_slice() {
+        [journal] => (journal.clone(), PathBuf::from("replay")),
+        [journal, output_dir] => (journal.clone(), output_dir.clone()),
+        _ => bail!("Usage: replay <journal.jsonl> [output-dir] [--final]"),
+    };
+
+    let mut replay = Replay::open(&journal)?;
+    println!("Replaying {} draw calls", replay.calls().len());
+
+    if only_final {
+        std::fs::create_dir_all(&output_dir)?;
+        replay.run_to_end();
+        replay.save_png(output_dir.join("final.png"))?;
+    } else {
+        replay.save_steps(&output_dir)?;
+    }
+
+    println!("Saved images to {}", output_dir.display());
+    Ok(())
+}
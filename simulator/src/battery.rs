@@ -0,0 +1,182 @@
+//! Simulated V5 battery.
+//!
+//! The battery starts at the capacity set in the [config](crate::config) and drains with the
+//! total current drawn by the simulated motors. Its voltage sags under load, following a simple
+//! model of an internal resistance in series with an open-circuit voltage which falls as the
+//! battery discharges. A physics provider can replace the model entirely by publishing a
+//! [`BatteryReading::Override`] with its device readings.
+
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use roboscope_ipc::{BatteryReading, BatterySnapshot};
+use tracing::trace;
+
+use crate::config;
+
+/// The shared battery state.
+///
+/// This lock must not be held while locking anything else.
+pub static BATTERY: Mutex<Battery> = Mutex::new(Battery::new());
+
+/// The charge of a fully charged battery, in milliamp-hours.
+const FULL_CHARGE_MAH: f64 = 1100.0;
+/// The open-circuit voltage of an empty battery, in volts.
+const EMPTY_VOLTAGE: f64 = 12.0;
+/// The open-circuit voltage of a fully charged battery, in volts.
+const FULL_VOLTAGE: f64 = 14.4;
+/// The internal resistance of the battery, in ohms.
+const INTERNAL_RESISTANCE: f64 = 0.1;
+/// The temperature of a battery which isn't being used, in degrees Celsius.
+const AMBIENT_TEMPERATURE: f64 = 25.0;
+/// How much warmer than ambient the battery gets per amp drawn, in degrees Celsius.
+const HEATING_PER_AMP: f64 = 1.5;
+
+/// The header shows the battery in a warning color at or below this capacity, in percent.
+pub const LOW_CAPACITY: f64 = 30.0;
+
+pub struct Battery {
+    /// Remaining capacity, in percent. `None` until the battery is first used, at which point
+    /// it's read from the config.
+    capacity: Option<f64>,
+    /// Total current drawn from the battery, in milliamps.
+    current: i32,
+    /// Reading published by the physics provider, which replaces the simulated state.
+    reading: BatteryReading,
+    last_update: Option<Instant>,
+}
+
+impl Battery {
+    pub const fn new() -> Self {
+        Self {
+            capacity: None,
+            current: 0,
+            reading: BatteryReading::Simulated,
+            last_update: None,
+        }
+    }
+
+    /// Drain the battery by the given current (in milliamps) for the time since the last update.
+    pub fn update(&mut self, current: i32) {
+        self.update_at(current, Instant::now());
+    }
+
+    fn update_at(&mut self, current: i32, now: Instant) {
+        let elapsed = self
+            .last_update
+            .replace(now)
+            .map_or(0.0, |last| (now - last).as_secs_f64());
+
+        let drained = f64::from(current.max(0)) * elapsed / 3600.0 / FULL_CHARGE_MAH * 100.0;
+        let capacity = self.simulated_capacity();
+        self.capacity = Some((capacity - drained).max(0.0));
+        self.current = current;
+
+        trace!(current, capacity = ?self.capacity, "Updated battery");
+    }
+
+    /// Use the battery reading sent by the physics provider.
+    pub fn set_reading(&mut self, reading: BatteryReading) {
+        self.reading = reading;
+    }
+
+    /// Get the current state of the battery.
+    pub fn snapshot(&self) -> BatterySnapshot {
+        if let BatteryReading::Override(snapshot) = self.reading {
+            return snapshot;
+        }
+
+        let capacity = self.simulated_capacity();
+        let amps = f64::from(self.current) / 1000.0;
+        let open_circuit = EMPTY_VOLTAGE + (FULL_VOLTAGE - EMPTY_VOLTAGE) * capacity / 100.0;
+
+        BatterySnapshot {
+            capacity,
+            voltage: ((open_circuit - amps * INTERNAL_RESISTANCE) * 1000.0) as i32,
+            current: self.current,
+            temperature: AMBIENT_TEMPERATURE + amps * HEATING_PER_AMP,
+        }
+    }
+
+    fn simulated_capacity(&self) -> f64 {
+        self.capacity.unwrap_or_else(|| config::get().battery_capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn full_battery() -> Battery {
+        Battery {
+            capacity: Some(100.0),
+            ..Battery::new()
+        }
+    }
+
+    #[test]
+    fn constant_current_drains_capacity() {
+        let mut battery = full_battery();
+        let start = Instant::now();
+
+        // 2.2A for 6 minutes uses 220mAh, which is 20% of a full charge.
+        battery.update_at(2200, start);
+        for minute in 1..=6 {
+            battery.update_at(2200, start + Duration::from_secs(minute * 60));
+        }
+
+        let capacity = battery.snapshot().capacity;
+        assert!((capacity - 80.0).abs() < 1e-9, "{capacity}");
+
+        // Nothing is drained while the motors are idle.
+        battery.update_at(0, start + Duration::from_secs(3600));
+        battery.update_at(0, start + Duration::from_secs(7200));
+        let idle_capacity = battery.snapshot().capacity;
+        assert!((idle_capacity - capacity).abs() < 0.1, "{idle_capacity}");
+    }
+
+    #[test]
+    fn voltage_sags_under_load() {
+        let mut battery = full_battery();
+        let now = Instant::now();
+
+        battery.update_at(0, now);
+        let idle = battery.snapshot();
+        assert_eq!(idle.voltage, 14_400);
+        assert_eq!(idle.temperature, AMBIENT_TEMPERATURE);
+
+        // 5A through the internal resistance drops 0.5V.
+        battery.update_at(5000, now);
+        let loaded = battery.snapshot();
+        assert!((idle.voltage - loaded.voltage - 500).abs() <= 1, "{}", loaded.voltage);
+        assert_eq!(loaded.current, 5000);
+        assert!(loaded.temperature > idle.temperature);
+
+        battery.update_at(0, now);
+        assert_eq!(battery.snapshot(), idle);
+    }
+
+    #[test]
+    fn override_takes_precedence() {
+        let mut battery = full_battery();
+        let now = Instant::now();
+        battery.update_at(3000, now);
+        let simulated = battery.snapshot();
+
+        let reading = BatterySnapshot {
+            capacity: 42.0,
+            voltage: 12_600,
+            current: 800,
+            temperature: 31.0,
+        };
+        battery.set_reading(BatteryReading::Override(reading));
+        battery.update_at(3000, now + Duration::from_secs(60));
+        assert_eq!(battery.snapshot(), reading);
+
+        // The simulated battery kept draining in the background.
+        battery.set_reading(BatteryReading::Simulated);
+        assert!(battery.snapshot().capacity < simulated.capacity);
+    }
+}
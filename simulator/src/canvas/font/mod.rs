@@ -1,4 +1,5 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
     fmt::{self, Debug, Formatter},
     ops::RangeInclusive,
@@ -14,11 +15,13 @@ use font_kit::{
     loaders::freetype::Font,
     metrics::Metrics,
 };
+use parking_lot::RwLock;
 use pathfinder_geometry::{
     rect::{RectF, RectI},
     transform2d::Transform2F,
     vector::Vector2I,
 };
+use tracing::debug;
 
 static FONT_MAP: &[(&str, f32, &[u8])] = &[
     (
@@ -45,6 +48,21 @@ static FONT_MAP: &[(&str, f32, &[u8])] = &[
 
 const PRE_RENDERED_CHARS: RangeInclusive<char> = (32u8 as char)..=(126u8 as char);
 
+/// The character drawn in place of characters which aren't in a font.
+///
+/// What VEXos draws for missing glyphs hasn't been checked on a Brain, so this is a placeholder
+/// rather than a match for the real display.
+const REPLACEMENT_CHAR: char = '.';
+
+/// The maximum number of characters outside of printable ASCII kept rasterized for each font.
+const MAX_EXTRA_CHARACTERS: usize = 512;
+
+thread_local! {
+    /// Fonts parsed on this thread for rasterizing extra characters, by ID. FreeType faces can't
+    /// be shared between threads, so each thread parses a font the first time it needs one.
+    static PARSED_FONTS: RefCell<HashMap<&'static str, Font>> = RefCell::new(HashMap::new());
+}
+
 pub static FONTS: LazyLock<FontLoader> = LazyLock::new(FontLoader::new);
 
 pub struct FontLoader {
@@ -57,9 +75,8 @@ impl FontLoader {
 
         for &(font_name, point_size, data) in FONT_MAP {
             let data = Arc::new(Vec::from(data));
-            let font = Font::from_bytes(data.clone(), 0).expect("bundled fonts are valid");
-
-            fonts.insert(font_name, Arc::new(PreRenderedFont::new(&font, point_size)));
+            let font = PreRenderedFont::new(font_name, data, point_size);
+            fonts.insert(font_name, Arc::new(font));
         }
 
         // Font name aliases for public API
@@ -75,26 +92,48 @@ impl FontLoader {
 }
 
 pub struct PreRenderedFont {
+    /// The name this font is registered under in [`FONTS`], e.g. `NotoMono_49pt`.
+    id: &'static str,
     name: String,
     point_size: f32,
     metrics: Metrics,
-    characters: Vec<RasterizedGlyph>,
+    /// The font file the glyphs are rasterized from.
+    data: Arc<Vec<u8>>,
+    /// Printable ASCII characters, which are rasterized as soon as the font is loaded.
+    characters: Vec<Arc<RasterizedGlyph>>,
+    /// Any other characters, which are rasterized the first time they're drawn. Characters the
+    /// font doesn't have a glyph for are stored as `None` so they aren't looked up again. The cache
+    /// is cleared once it holds [`MAX_EXTRA_CHARACTERS`] entries.
+    extra_characters: RwLock<HashMap<char, Option<Arc<RasterizedGlyph>>>>,
 }
 
 impl PreRenderedFont {
-    pub fn new(font: &Font, point_size: f32) -> Self {
+    pub fn new(id: &'static str, data: Arc<Vec<u8>>, point_size: f32) -> Self {
+        let font = Font::from_bytes(data.clone(), 0).expect("bundled fonts are valid");
         let metrics = font.metrics();
 
         Self {
+            id,
             name: font.full_name(),
             point_size,
             characters: PRE_RENDERED_CHARS
-                .map(|character| RasterizedGlyph::new(font, &metrics, point_size, character))
+                .map(|character| {
+                    let glyph = RasterizedGlyph::new(&font, &metrics, point_size, character)
+                        .expect("bundled fonts have glyphs for printable ASCII");
+                    Arc::new(glyph)
+                })
                 .collect(),
+            extra_characters: RwLock::default(),
             metrics,
+            data,
         }
     }
 
+    /// Get the name used to select this font with [`FontLoader::get`].
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -115,13 +154,57 @@ impl PreRenderedFont {
             as u32
     }
 
-    pub fn glyph_for_char(&self, character: char) -> &RasterizedGlyph {
-        let replacement_char = b'.' - *PRE_RENDERED_CHARS.start() as u8;
+    /// Get the glyph used to draw the given character.
+    ///
+    /// Characters outside of the printable ASCII range are rasterized the first time they are
+    /// requested and cached for later use. If the font doesn't have a glyph for the character, the
+    /// glyph for [`REPLACEMENT_CHAR`] is returned instead.
+    pub fn glyph_for_char(&self, character: char) -> Arc<RasterizedGlyph> {
+        if let Some(glyph) = self.pre_rendered_glyph(character) {
+            return glyph.clone();
+        }
 
-        let idx = character as u8 - *PRE_RENDERED_CHARS.start() as u8;
-        self.characters
-            .get(idx as usize)
-            .unwrap_or(&self.characters[replacement_char as usize])
+        let cached = self.extra_characters.read().get(&character).cloned();
+        let glyph = cached.unwrap_or_else(|| self.rasterize_extra_char(character));
+
+        glyph.unwrap_or_else(|| {
+            self.pre_rendered_glyph(REPLACEMENT_CHAR)
+                .expect("replacement character is pre-rendered")
+                .clone()
+        })
+    }
+
+    fn pre_rendered_glyph(&self, character: char) -> Option<&Arc<RasterizedGlyph>> {
+        let idx = (character as u32).checked_sub(*PRE_RENDERED_CHARS.start() as u32)?;
+        self.characters.get(idx as usize)
+    }
+
+    /// Rasterize a character which isn't pre-rendered and add it to the cache.
+    fn rasterize_extra_char(&self, character: char) -> Option<Arc<RasterizedGlyph>> {
+        let glyph = PARSED_FONTS.with_borrow_mut(|fonts| {
+            let font = fonts.entry(self.id).or_insert_with(|| {
+                Font::from_bytes(self.data.clone(), 0).expect("bundled fonts are valid")
+            });
+            RasterizedGlyph::new(font, &self.metrics, self.point_size, character)
+        });
+
+        let glyph = glyph.map(Arc::new);
+        if glyph.is_some() {
+            debug!(?character, font = %self.name, "Rasterized glyph");
+        } else {
+            debug!(?character, font = %self.name, "Font has no glyph for character");
+        }
+
+        let mut extra_characters = self.extra_characters.write();
+        if extra_characters.len() >= MAX_EXTRA_CHARACTERS {
+            // Start over rather than tracking usage. Programs rarely draw more than a handful of
+            // distinct non-ASCII characters, so this should only happen with unusual text.
+            debug!(font = %self.name, "Clearing extra character cache");
+            extra_characters.clear();
+        }
+        extra_characters.insert(character, glyph.clone());
+
+        glyph
     }
 }
 
@@ -140,6 +223,10 @@ impl Debug for PreRenderedFont {
         f.debug_struct("PreRenderedFont")
             .field("point_size", &self.point_size)
             .field("characters", &RenderChars(self.characters.len()))
+            .field(
+                "extra_characters",
+                &self.extra_characters.read().keys().collect::<Vec<_>>(),
+            )
             .finish()
     }
 }
@@ -153,14 +240,17 @@ pub struct RasterizedGlyph {
 
 impl RasterizedGlyph {
     /// Rasterize the given character without any antialiasing.
-    pub fn new(font: &Font, metrics: &Metrics, point_size: f32, character: char) -> Self {
+    ///
+    /// Returns `None` if the font doesn't contain a glyph for the character.
+    pub fn new(font: &Font, metrics: &Metrics, point_size: f32, character: char) -> Option<Self> {
         let scale = point_size / metrics.units_per_em as f32;
 
         let transform = Transform2F::default();
         let hinting = HintingOptions::Full(point_size);
         let rasterization_options = RasterizationOptions::SubpixelAa;
 
-        let glyph_id = font.glyph_for_char(character).expect("missing character");
+        // Glyph zero is the font's "missing glyph" placeholder.
+        let glyph_id = font.glyph_for_char(character).filter(|&id| id != 0)?;
 
         let dims = font
             .raster_bounds(
@@ -170,7 +260,7 @@ impl RasterizedGlyph {
                 hinting,
                 rasterization_options,
             )
-            .expect("glyph should render");
+            .ok()?;
         let mut canvas = FontCanvas::new(dims.size(), Format::A8);
 
         // Move the character from its default offset to the upper left of the canvas so that none
@@ -184,7 +274,7 @@ impl RasterizedGlyph {
             hinting,
             rasterization_options,
         )
-        .expect("glyph should render");
+        .ok()?;
 
         for pixel in &mut canvas.pixels {
             // Make pixels brighter.
@@ -193,12 +283,12 @@ impl RasterizedGlyph {
             *pixel = *pixel / (255 / 3) * (255 / 3);
         }
 
-        Self {
+        Some(Self {
             size: dims.size(),
             offset: dims.origin(),
-            advance: font.advance(glyph_id).unwrap().x() * scale,
+            advance: font.advance(glyph_id).ok()?.x() * scale,
             bitmap: canvas.pixels,
-        }
+        })
     }
 
     /// Returns the dimensions of the glyph, scaled by the given fraction.
@@ -235,3 +325,53 @@ impl Debug for RasterizedGlyph {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> PreRenderedFont {
+        let &(id, point_size, data) = &FONT_MAP[2];
+        PreRenderedFont::new(id, Arc::new(Vec::from(data)), point_size)
+    }
+
+    fn is_replacement(font: &PreRenderedFont, glyph: &Arc<RasterizedGlyph>) -> bool {
+        Arc::ptr_eq(glyph, font.pre_rendered_glyph(REPLACEMENT_CHAR).unwrap())
+    }
+
+    #[test]
+    fn rasterizes_non_ascii() {
+        let font = font();
+
+        let glyph = font.glyph_for_char('é');
+        assert!(!is_replacement(&font, &glyph));
+        assert!(glyph.bitmap.iter().any(|&pixel| pixel != 0));
+
+        // The second lookup is served from the cache.
+        assert!(Arc::ptr_eq(&glyph, &font.glyph_for_char('é')));
+        assert!(font.extra_characters.read()[&'é'].is_some());
+    }
+
+    #[test]
+    fn missing_glyph_uses_replacement() {
+        let font = font();
+
+        // Private use area, which none of the bundled fonts have glyphs for.
+        let glyph = font.glyph_for_char('\u{E000}');
+        assert!(is_replacement(&font, &glyph));
+        assert!(font.extra_characters.read()[&'\u{E000}'].is_none());
+    }
+
+    #[test]
+    fn cache_is_bounded() {
+        let font = font();
+
+        for character in ('\u{E000}'..).take(MAX_EXTRA_CHARACTERS + 10) {
+            font.glyph_for_char(character);
+            assert!(font.extra_characters.read().len() <= MAX_EXTRA_CHARACTERS);
+        }
+
+        // The cache was cleared when it filled up, so only the characters after that are left.
+        assert_eq!(font.extra_characters.read().len(), 10);
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    env,
     fmt::{self, Formatter},
     mem,
     ops::RangeInclusive,
@@ -11,12 +10,17 @@ use fast_image_resize::{
     images::{TypedCroppedImageMut, TypedImage},
     pixels::U8,
 };
-use image::GrayImage;
+use image::{GrayImage, RgbImage};
 use line_drawing::{Bresenham, BresenhamCircle};
 use parking_lot::Mutex;
-use tracing::trace;
+use roboscope_ipc::FrameRect;
+use tracing::{trace, warn};
 
-use crate::canvas::font::{FONTS, PreRenderedFont};
+use crate::{
+    canvas::font::{FONTS, PreRenderedFont},
+    config::{self, Config, DebugFlag, DisplayTheme},
+    sdk::{MAX_TASKS, current_task_index},
+};
 
 mod font;
 pub mod img;
@@ -29,9 +33,61 @@ pub const BUFSZ: usize = WIDTH as usize * HEIGHT as usize;
 const TEXT_WIDTH: u32 = 512;
 const TEXT_BUFSZ: usize = TEXT_WIDTH as usize * TEXT_WIDTH as usize;
 
-pub const DEFAULT_BG_COLOR: u32 = 0x00_00_00;
-pub const DEFAULT_FG_COLOR: u32 = 0xFF_FF_FF;
-pub const HEADER_COLOR: u32 = 0x00_99_CC;
+/// The colors used to draw the display in a particular [`DisplayTheme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThemeColors {
+    /// The default background color of the user canvas, which it's filled with at startup.
+    pub background: u32,
+    /// The default foreground color of the user canvas.
+    pub foreground: u32,
+    /// The background of the program header.
+    pub header: u32,
+    /// The program name and elapsed time in the header, and the outline of the battery icon.
+    pub header_text: u32,
+    /// The fill color of the battery icon.
+    pub battery: u32,
+    /// The fill color of the battery icon when the battery is low.
+    pub battery_low: u32,
+}
+
+impl ThemeColors {
+    pub const DARK: Self = Self {
+        background: 0x00_00_00,
+        foreground: 0xFF_FF_FF,
+        header: 0x00_99_CC,
+        header_text: 0x00_00_00,
+        battery: 0x93_C8_3F,
+        battery_low: 0xFF_D4_00,
+    };
+
+    pub const LIGHT: Self = Self {
+        background: 0xFF_FF_FF,
+        foreground: 0x00_00_00,
+        header: 0xD9_EE_F7,
+        header_text: 0x00_00_00,
+        battery: 0x6A_B0_23,
+        battery_low: 0xE8_B4_00,
+    };
+
+    pub const fn for_theme(theme: DisplayTheme) -> Self {
+        match theme {
+            DisplayTheme::Dark => Self::DARK,
+            DisplayTheme::Light => Self::LIGHT,
+        }
+    }
+
+    /// Get the colors of the configured theme, with the default canvas colors swapped if the
+    /// program asked for inverted colors.
+    pub fn from_config(config: &Config) -> Self {
+        let mut colors = Self::for_theme(config.theme);
+
+        if config.invert_colors {
+            mem::swap(&mut colors.foreground, &mut colors.background);
+        }
+
+        colors
+    }
+}
 
 /// The canvas instance used by user code.
 pub static CANVAS: LazyLock<Mutex<Canvas>> = LazyLock::new(|| Mutex::new(Canvas::new()));
@@ -40,12 +96,15 @@ pub static CANVAS: LazyLock<Mutex<Canvas>> = LazyLock::new(|| Mutex::new(Canvas:
 pub struct CanvasState {
     pub fg_color: u32,
     pub bg_color: u32,
-    // This doesn't seem to affect any operations, but we store it so we can return it from
-    // `vexDisplayPenSizeGet`.
+    /// The width in pixels of lines drawn by outline operations such as [`Canvas::draw_line`],
+    /// [`Canvas::draw_rect`] and [`Canvas::draw_circle`].
     pub pen_size: u32,
 
-    /// The region where changes to the canvas are allowed.
-    clip_region: Rect,
+    /// The regions where changes to the canvas are allowed, indexed by VEXos task.
+    ///
+    /// Draw operations use the clip region belonging to the task that is currently running (see
+    /// [`current_task_index`]).
+    clip_regions: [Rect; MAX_TASKS],
 
     /// A set of glyphs rendered at a certain point size which can be scaled up or down to draw
     /// text.
@@ -64,14 +123,30 @@ impl CanvasState {
         mem::swap(&mut self.fg_color, &mut self.bg_color);
     }
 
-    pub fn set_clip_region(&mut self, mut region: Rect) {
+    /// Set the clip region of the task that is currently running.
+    pub fn set_clip_region(&mut self, region: Rect) {
+        self.set_task_clip_region(current_task_index(), region);
+    }
+
+    /// Set the clip region used when the task with the given index draws to the canvas.
+    ///
+    /// If the index is out of range, a warning is logged and the clip region is ignored.
+    pub fn set_task_clip_region(&mut self, task: usize, mut region: Rect) {
         region.clip_to(Rect::FULL_CLIP);
-        self.clip_region = region;
+
+        let Some(clip_region) = self.clip_regions.get_mut(task) else {
+            warn!(task, ?region, "Ignoring clip region for out of range task index");
+            return;
+        };
+        *clip_region = region;
     }
 
-    /// Get the current clip region of the
+    /// Get the clip region of the task that is currently running.
     pub fn clip_region(&self) -> Rect {
-        self.clip_region
+        self.clip_regions
+            .get(current_task_index())
+            .copied()
+            .unwrap_or(Rect::FULL_CLIP)
     }
 
     /// Set the current font by name.
@@ -90,33 +165,39 @@ pub struct Canvas {
     /// Scratch buffer for planning text writes before compositing them onto the main buffer. Holds
     /// opacity values for the text.
     text_buffer: Box<[u8; TEXT_BUFSZ]>,
+    /// The region of `buffer` which has been drawn to since the last call to
+    /// [`Canvas::take_dirty`].
+    dirty: Option<Rect>,
     debug_text_buffer: bool,
     pub state: CanvasState,
     pub saved_state: CanvasState,
 }
 
 impl Canvas {
+    /// Create a canvas using the default colors of the configured display theme.
     pub fn new() -> Self {
+        Self::with_colors(ThemeColors::from_config(config::get()))
+    }
+
+    /// Create a canvas filled with the background color of the given theme colors.
+    pub fn with_colors(colors: ThemeColors) -> Self {
         let state = CanvasState {
-            fg_color: DEFAULT_FG_COLOR,
-            bg_color: DEFAULT_BG_COLOR,
-            clip_region: Rect::FULL_CLIP,
+            fg_color: colors.foreground,
+            bg_color: colors.background,
+            clip_regions: [Rect::FULL_CLIP; MAX_TASKS],
             pen_size: 1,
             font: FONTS.get("monospace").unwrap(),
             font_scale: (1, 3),
         };
 
-        let debug_env = env::var("SIM_DEBUG").unwrap_or_default();
-
-        let debug_toggles: Vec<&str> = debug_env.split(',').collect();
-
         Self {
             // Allocate directly on the heap to prevent a stack overflow.
-            buffer: vec![0u32; BUFSZ].into_boxed_slice().try_into().unwrap(),
+            buffer: vec![colors.background; BUFSZ].into_boxed_slice().try_into().unwrap(),
             text_buffer: vec![0u8; TEXT_BUFSZ].into_boxed_slice().try_into().unwrap(),
+            dirty: None,
             saved_state: state.clone(),
             state,
-            debug_text_buffer: debug_toggles.contains(&"text_buffer"),
+            debug_text_buffer: config::get().debug_enabled(DebugFlag::TextBuffer),
         }
     }
 
@@ -129,23 +210,44 @@ impl Canvas {
     }
 
     pub fn set_pixel(&mut self, point: Point) {
-        if !point.is_inside(self.state.clip_region) {
+        if !point.is_inside(self.state.clip_region()) {
             return;
         }
 
-        trace!(color = %Hex(self.state.fg_color), ?point, "update pixel");
         self.write_pixel(point, self.state.fg_color);
     }
 
     fn write_pixel(&mut self, point: Point, color: u32) {
+        self.mark_dirty(Rect::sized(point.x, point.y, 1, 1));
+
         let idx = point.y * WIDTH as i32 + point.x;
         self.buffer[idx as usize] = color;
     }
 
+    /// Extend the dirty region of the canvas to include the given rectangle.
+    fn mark_dirty(&mut self, region: Rect) {
+        if region.is_empty() {
+            return;
+        }
+
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(region),
+            None => region,
+        });
+    }
+
+    /// Get the region of the canvas which has been drawn to since the last time this function was
+    /// called, resetting it to be empty.
+    ///
+    /// Returns `None` if the canvas hasn't changed.
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
     pub fn draw_horizontal_line(&mut self, x_range: RangeInclusive<i32>, y: i32) {
         trace!(?x_range, y, "horizontal line");
 
-        let clip = self.state.clip_region;
+        let clip = self.state.clip_region();
 
         // Is the line above or below the clip region?
         if !(clip.0.y..clip.1.y).contains(&y) {
@@ -166,7 +268,7 @@ impl Canvas {
     pub fn draw_vertical_line(&mut self, x: i32, y_range: RangeInclusive<i32>) {
         trace!(x, ?y_range, "vertical line");
 
-        let clip = self.state.clip_region;
+        let clip = self.state.clip_region();
 
         // Is the line left or right of the clip region?
         if !(clip.0.x..clip.1.x).contains(&x) {
@@ -184,36 +286,72 @@ impl Canvas {
         }
     }
 
+    /// Draw a line between two points using the current pen size.
+    ///
+    /// The placement of the extra pixels for pens wider than 1px is a guess which hasn't been
+    /// checked against VEXos yet. `pen_size_goldens` in this module compares it with captures from
+    /// a V5 Brain under `tests/golden/pen-size` once they are available.
     pub fn draw_line(&mut self, start: Point, end: Point) {
-        trace!(?start, ?end, "line");
+        trace!(?start, ?end, pen_size = self.state.pen_size, "line");
+
+        let pen_size = self.state.pen_size.max(1) as i32;
+
+        // Thick lines are drawn by stamping a square pen centered on each point of the line. When
+        // the pen size is even, the extra row and column go below and to the right of the line.
+        let pen_offset = (pen_size - 1) / 2;
 
         for (x, y) in Bresenham::new((start.x, start.y), (end.x, end.y)) {
-            self.set_pixel(Point { x, y });
+            if pen_size == 1 {
+                self.set_pixel(Point { x, y });
+            } else {
+                self.fill_rect(Rect::sized(
+                    x - pen_offset,
+                    y - pen_offset,
+                    pen_size,
+                    pen_size,
+                ));
+            }
         }
     }
 
     pub fn fill_rect(&mut self, mut bounds: Rect) {
         trace!(color = %Hex(self.state.fg_color), ?bounds, "fill rect");
 
-        bounds.clip_to(self.state.clip_region);
+        bounds.clip_to(self.state.clip_region());
 
         for pixel in bounds.pixels() {
             self.write_pixel(pixel, self.state.fg_color);
         }
     }
 
+    /// Draw the outline of a rectangle using the current pen size.
+    ///
+    /// Like [`Canvas::draw_line`], outlines wider than 1px haven't been verified against hardware.
     pub fn draw_rect(&mut self, bounds: Rect) {
-        trace!(color = %Hex(self.state.fg_color), ?bounds, "trace rect");
+        trace!(
+            color = %Hex(self.state.fg_color),
+            ?bounds,
+            pen_size = self.state.pen_size,
+            "trace rect"
+        );
 
-        let horizontal_lines = [bounds.0.y, bounds.1.y - 1];
-        let vertical_lines = [bounds.0.x, bounds.1.x - 1];
+        // Thick outlines grow towards the inside of the rectangle so that its outer edge stays in
+        // the same place regardless of the pen size. Pens wider than half of the rectangle just
+        // end up filling it.
+        let max_pen_size = (bounds.width().min(bounds.height()) + 1) / 2;
+        let pen_size = (self.state.pen_size.max(1) as i32).min(max_pen_size);
 
-        for y in horizontal_lines {
-            self.draw_horizontal_line(bounds.0.x..=(bounds.1.x - 1), y);
-        }
+        for offset in 0..pen_size {
+            let horizontal_lines = [bounds.0.y + offset, bounds.1.y - 1 - offset];
+            let vertical_lines = [bounds.0.x + offset, bounds.1.x - 1 - offset];
 
-        for x in vertical_lines {
-            self.draw_vertical_line(x, bounds.0.y..=(bounds.1.y - 1));
+            for y in horizontal_lines {
+                self.draw_horizontal_line(bounds.0.x..=(bounds.1.x - 1), y);
+            }
+
+            for x in vertical_lines {
+                self.draw_vertical_line(x, bounds.0.y..=(bounds.1.y - 1));
+            }
         }
     }
 
@@ -226,36 +364,24 @@ impl Canvas {
             self.set_pixel(center);
         }
 
-        // Turn the circle into a bunch of horizontal lines by using Bresenham's circle
-        // algorithm to find the left and right extents of each line.
-
-        // The center point isn't included in the radius, so it gets its own extra line.
-        let num_lines = 1 + radius * 2;
-        let mut lines = vec![(center.x, center.x); num_lines as usize];
-
-        for (dx, i) in BresenhamCircle::new(0, radius as i32, radius as i32) {
-            let x = center.x + dx;
-
-            // The tops and bottoms of circles have several points on the same line, so only record
-            // the leftmost or rightmost point for our horizontal line.
-            if dx < 0 {
-                if x < lines[i as usize].0 {
-                    lines[i as usize].0 = x;
-                }
-            } else if x > lines[i as usize].1 {
-                lines[i as usize].1 = x;
-            }
-        }
-
-        // Iterate through each line and draw it.
-        for (line, (left, right)) in lines.into_iter().enumerate() {
+        // Turn the circle into a bunch of horizontal lines and draw each of them.
+        for (line, (left, right)) in circle_spans(radius).into_iter().enumerate() {
             let y = center.y - radius as i32 + line as i32;
-            self.draw_horizontal_line(left..=right, y);
+            self.draw_horizontal_line((center.x + left)..=(center.x + right), y);
         }
     }
 
+    /// Draw the outline of a circle using the current pen size.
+    ///
+    /// Like [`Canvas::draw_line`], outlines wider than 1px haven't been verified against hardware.
     pub fn draw_circle(&mut self, center: Point, radius: u32) {
-        trace!(color = %Hex(self.state.fg_color), ?center, radius, "trace circle");
+        trace!(
+            color = %Hex(self.state.fg_color),
+            ?center,
+            radius,
+            pen_size = self.state.pen_size,
+            "trace circle"
+        );
 
         // Special case to treat radius zero as a set_pixel call since using Bresenham would just
         // give us an empty iterator.
@@ -263,7 +389,14 @@ impl Canvas {
             self.set_pixel(center);
         }
 
-        let clip = self.state.clip_region;
+        let pen_size = self.state.pen_size.max(1);
+
+        if pen_size > 1 {
+            self.draw_thick_circle(center, radius, pen_size);
+            return;
+        }
+
+        let clip = self.state.clip_region();
 
         for (x, y) in BresenhamCircle::new(center.x, center.y, radius as i32) {
             if (Point { x, y }).is_inside(clip) {
@@ -272,16 +405,55 @@ impl Canvas {
         }
     }
 
+    /// Draw the outline of a circle as a ring which is `pen_size` pixels wide.
+    ///
+    /// Like rectangles, the outline grows inward so the outer edge of the ring matches the edge of
+    /// a 1px-wide outline with the same radius. This is an assumption about what VEXos does, not
+    /// something that has been measured.
+    fn draw_thick_circle(&mut self, center: Point, radius: u32, pen_size: u32) {
+        let Some(inner_radius) = radius.checked_sub(pen_size) else {
+            // The pen is wide enough to cover the entire inside of the circle.
+            self.fill_circle(center, radius);
+            return;
+        };
+
+        let outer_spans = circle_spans(radius);
+        let inner_spans = circle_spans(inner_radius);
+
+        for (line, (left, right)) in outer_spans.into_iter().enumerate() {
+            let dy = line as i32 - radius as i32;
+            let y = center.y + dy;
+
+            // Rows which also pass through the hollow inside of the ring are split into a left and
+            // right segment, everything else is a solid line.
+            let inner_line = usize::try_from(dy + inner_radius as i32).ok();
+            let Some(&(inner_left, inner_right)) = inner_line.and_then(|i| inner_spans.get(i))
+            else {
+                self.draw_horizontal_line((center.x + left)..=(center.x + right), y);
+                continue;
+            };
+
+            if left < inner_left {
+                self.draw_horizontal_line((center.x + left)..=(center.x + inner_left - 1), y);
+            }
+            if inner_right < right {
+                self.draw_horizontal_line((center.x + inner_right + 1)..=(center.x + right), y);
+            }
+        }
+    }
+
     pub unsafe fn copy_rect(&mut self, mut bounds: Rect, source: *const u32, stride: usize) {
         trace!(?bounds, ?source, ?stride, "copy rect");
         let origin = bounds.0;
-        bounds.clip_to(self.state.clip_region);
+        bounds.clip_to(self.state.clip_region());
 
         // When the top/left of the bounds is clipped off, begin part-way through the
         // source image rather than showing the beginning of it lower.
         let col_offset = (bounds.0.x - origin.x) as usize;
         let row_offset = (bounds.0.y - origin.y) as usize;
 
+        self.mark_dirty(bounds);
+
         for (row_idx, row) in (bounds.0.y..bounds.1.y).enumerate() {
             for (col_idx, col) in (bounds.0.x..bounds.1.x).enumerate() {
                 let dest_idx = row * WIDTH as i32 + col;
@@ -363,7 +535,7 @@ impl Canvas {
         // Now that we've set up our mask, we need to apply it on the real canvas.
 
         let mut dest_bounds = Rect::sized(origin.x, origin.y, x_cursor, height as i32);
-        dest_bounds.clip_to(self.state.clip_region);
+        dest_bounds.clip_to(self.state.clip_region());
 
         if opaque {
             self.state.swap_colors();
@@ -371,6 +543,8 @@ impl Canvas {
             self.state.swap_colors();
         }
 
+        self.mark_dirty(dest_bounds);
+
         let [_, cr, cg, cb] = self.state.fg_color.to_be_bytes();
 
         for Point { x, y } in dest_bounds.pixels() {
@@ -416,6 +590,23 @@ impl Canvas {
         &self.buffer
     }
 
+    /// Save the contents of the canvas as a PNG image.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let pixels = self
+            .buffer
+            .iter()
+            .flat_map(|pixel| {
+                let [_, r, g, b] = pixel.to_be_bytes();
+                [r, g, b]
+            })
+            .collect();
+
+        let img = RgbImage::from_raw(WIDTH, HEIGHT, pixels)
+            .expect("canvas dimensions are always valid");
+        img.save(path.as_ref())?;
+        Ok(())
+    }
+
     /// Save the contents of `text_buffer` as a grayscale PNG for debugging.
     pub fn save_text_buffer_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
         let img = GrayImage::from_raw(TEXT_WIDTH, TEXT_WIDTH, self.text_buffer.to_vec())
@@ -450,7 +641,7 @@ impl Point {
         self.y = self.y.clamp(region.0.y, region.1.y - 1);
     }
 
-    fn is_inside(&self, region: Rect) -> bool {
+    pub fn is_inside(&self, region: Rect) -> bool {
         (region.0.x..region.1.x).contains(&self.x) && (region.0.y..region.1.y).contains(&self.y)
     }
 }
@@ -461,6 +652,7 @@ pub struct Rect(pub Point, pub Point);
 
 impl Rect {
     pub const FULL_CLIP: Self = Rect::new(0, 0, WIDTH as i32, HEIGHT as i32);
+    #[allow(dead_code, reason = "only used by golden-image tests")]
     pub const USER_CLIP: Self = Rect::new(0, HEADER_HEIGHT, WIDTH as i32, HEIGHT as i32);
     pub const HEADER_CLIP: Self = Rect::new(0, 0, WIDTH as i32, HEADER_HEIGHT);
 
@@ -520,18 +712,77 @@ impl Rect {
         self.1.y - self.0.y
     }
 
+    /// Returns `true` if the rectangle doesn't contain any pixels.
+    pub fn is_empty(&self) -> bool {
+        self.width() <= 0 || self.height() <= 0
+    }
+
     /// Shrink this rectangle to be completely enclosed by the given other rectangle.
     pub fn clip_to(&mut self, region: Rect) {
         self.0.clamp_to(region);
         self.1.clamp_to(region);
     }
 
+    /// Get the smallest rectangle which contains both this rectangle and the given other one.
+    ///
+    /// Empty rectangles don't contain any pixels, so they don't affect the result.
+    pub fn union(&self, other: Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        } else if self.is_empty() {
+            return other;
+        }
+
+        Rect(
+            Point {
+                x: self.0.x.min(other.0.x),
+                y: self.0.y.min(other.0.y),
+            },
+            Point {
+                x: self.1.x.max(other.1.x),
+                y: self.1.y.max(other.1.y),
+            },
+        )
+    }
+
+    /// Get the region where this rectangle overlaps with the given other one, or `None` if they
+    /// don't overlap.
+    pub fn intersection(&self, other: Rect) -> Option<Rect> {
+        let overlap = Rect(
+            Point {
+                x: self.0.x.max(other.0.x),
+                y: self.0.y.max(other.0.y),
+            },
+            Point {
+                x: self.1.x.min(other.1.x),
+                y: self.1.y.min(other.1.y),
+            },
+        );
+
+        (!overlap.is_empty()).then_some(overlap)
+    }
+
     /// Iterate over the pixels in this rectangle, row-by-row.
     pub fn pixels(&self) -> impl Iterator<Item = Point> {
         (self.0.y..self.1.y).flat_map(|y| (self.0.x..self.1.x).map(move |x| Point { x, y }))
     }
 }
 
+impl From<Rect> for FrameRect {
+    fn from(rect: Rect) -> Self {
+        let Some(rect) = rect.intersection(Rect::FULL_CLIP) else {
+            return Self::default();
+        };
+
+        Self {
+            x: rect.left() as u32,
+            y: rect.top() as u32,
+            width: rect.width() as u32,
+            height: rect.height() as u32,
+        }
+    }
+}
+
 struct Hex(u32);
 impl std::fmt::Display for Hex {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -539,6 +790,30 @@ impl std::fmt::Display for Hex {
     }
 }
 
+/// Find the horizontal extents of each row of a filled circle relative to its center point.
+///
+/// This uses Bresenham's circle algorithm to find the left and right edges of the circle. The
+/// returned list has `1 + radius * 2` entries and is ordered from top to bottom.
+fn circle_spans(radius: u32) -> Vec<(i32, i32)> {
+    // The center point isn't included in the radius, so it gets its own extra line.
+    let num_lines = 1 + radius * 2;
+    let mut lines = vec![(0, 0); num_lines as usize];
+
+    for (dx, i) in BresenhamCircle::new(0, radius as i32, radius as i32) {
+        let line = &mut lines[i as usize];
+
+        // The tops and bottoms of circles have several points on the same line, so only record
+        // the leftmost or rightmost point for our horizontal line.
+        if dx < 0 {
+            line.0 = line.0.min(dx);
+        } else {
+            line.1 = line.1.max(dx);
+        }
+    }
+
+    lines
+}
+
 /// Clamps `source` to the range `region`, or returns `None` if source is completely outside
 /// `region`.
 ///
@@ -571,3 +846,172 @@ fn clamp_range<T: PartialOrd + Copy>(
 
     Some(begin..=end)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, thread};
+
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::{
+        sdk::{
+            set_current_task_index, vexDisplayCircleDraw, vexDisplayForegroundColor,
+            vexDisplayLineDraw, vexDisplayPenSizeSet, vexDisplayRectDraw,
+        },
+        testing::DisplayTest,
+    };
+
+    fn pixel(canvas: &Canvas, x: i32, y: i32) -> u32 {
+        canvas.buffer()[(y * WIDTH as i32 + x) as usize]
+    }
+
+    #[test]
+    fn rect_union() {
+        let a = Rect::sized(10, 10, 20, 20);
+        let b = Rect::sized(100, 50, 10, 10);
+
+        assert_eq!(a.union(b), Rect::new(10, 10, 110, 60));
+        assert_eq!(b.union(a), Rect::new(10, 10, 110, 60));
+        assert_eq!(a.union(a), a);
+
+        let empty = Rect::sized(200, 200, 0, 5);
+        assert_eq!(a.union(empty), a);
+        assert_eq!(empty.union(a), a);
+    }
+
+    #[test]
+    fn rect_intersection() {
+        let a = Rect::sized(10, 10, 20, 20);
+
+        assert_eq!(a.intersection(Rect::sized(20, 0, 20, 20)), Some(Rect::new(20, 10, 30, 20)));
+        assert_eq!(a.intersection(a), Some(a));
+
+        // Disjoint and touching rectangles don't share any pixels.
+        assert_eq!(a.intersection(Rect::sized(100, 100, 5, 5)), None);
+        assert_eq!(a.intersection(Rect::sized(30, 10, 5, 5)), None);
+        assert_eq!(a.intersection(Rect::sized(15, 15, 0, 0)), None);
+    }
+
+    #[test]
+    fn rect_is_empty() {
+        assert!(!Rect::sized(0, 0, 1, 1).is_empty());
+        assert!(Rect::sized(5, 5, 0, 10).is_empty());
+        assert!(Rect::sized(5, 5, 10, 0).is_empty());
+        assert!(Rect(Point::new(10, 10), Point::new(5, 20)).is_empty());
+        assert!(!Rect::FULL_CLIP.is_empty());
+    }
+
+    #[test]
+    fn dirty_region_is_clipped() {
+        let mut canvas = Canvas::new();
+        canvas.take_dirty();
+
+        let clip = Rect::sized(100, 100, 50, 50);
+        canvas.state.set_clip_region(clip);
+
+        // Clipping stops one pixel short of the right and bottom edges of the clip region (see
+        // `Point::clamp_to`), so the dirty region does too.
+        let right_edge = clip.1.x - 1;
+
+        let pixels = [0x12_34_56; 40 * 40];
+        // SAFETY: the pixels cover the entire 40x40 rectangle with a stride of 40.
+        unsafe { canvas.copy_rect(Rect::sized(130, 80, 40, 40), pixels.as_ptr(), 40) };
+        assert_eq!(canvas.take_dirty(), Some(Rect::new(130, 100, right_edge, 120)));
+
+        unsafe { canvas.copy_rect(Rect::sized(300, 80, 40, 40), pixels.as_ptr(), 40) };
+        assert_eq!(canvas.take_dirty(), None);
+
+        // Text running past the right edge of the clip region only dirties the part inside it.
+        canvas.draw_string(Point { x: 140, y: 110 }, "Hello, world!", false);
+        let dirty = canvas.take_dirty().unwrap();
+        assert_eq!(dirty.left(), 140);
+        assert_eq!(dirty.top(), 110);
+        assert_eq!(dirty.1.x, right_edge);
+        assert!(dirty.1.y < clip.1.y);
+
+        canvas.draw_string(Point { x: 300, y: 110 }, "Hello, world!", true);
+        assert_eq!(canvas.take_dirty(), None);
+    }
+
+    #[test]
+    fn tasks_clip_independently() {
+        let canvas = Mutex::new(Canvas::new());
+        let background = canvas.lock().state.bg_color;
+
+        {
+            let mut canvas = canvas.lock();
+            canvas.state.set_task_clip_region(0, Rect::sized(0, 40, 100, 100));
+            canvas.state.set_task_clip_region(3, Rect::sized(200, 40, 100, 100));
+        }
+
+        let fill_as_task = |task, color| {
+            set_current_task_index(task);
+
+            let mut canvas = canvas.lock();
+            canvas.state.fg_color = color;
+            canvas.fill_rect(Rect::FULL_CLIP);
+        };
+
+        thread::scope(|scope| {
+            scope.spawn(|| fill_as_task(0, 0xFF_00_00));
+            scope.spawn(|| fill_as_task(3, 0x00_00_FF));
+        });
+
+        let canvas = canvas.lock();
+        assert_eq!(pixel(&canvas, 50, 80), 0xFF_00_00);
+        assert_eq!(pixel(&canvas, 250, 80), 0x00_00_FF);
+        assert_eq!(pixel(&canvas, 150, 80), background);
+        assert_eq!(pixel(&canvas, 50, 200), background);
+        assert_eq!(pixel(&canvas, 250, 200), background);
+    }
+
+    const PEN_SIZES: [u32; 3] = [1, 2, 5];
+
+    /// Draw one shape with each pen size and compare the display with the image captured on a
+    /// V5 Brain running the same program.
+    fn check_pen_sizes(shape: &str, draw: fn()) {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/pen-size");
+
+        for pen_size in PEN_SIZES {
+            let mut test = DisplayTest::start(&format!("{shape}-{pen_size}"), move || {
+                vexDisplayForegroundColor(0xFF_FF_FF);
+                vexDisplayPenSizeSet(pen_size);
+                draw();
+            });
+            test.run_to_completion();
+            test.assert_matches_golden(dir.join(format!("{shape}-{pen_size}.png")));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs captures from a V5 Brain in tests/golden/pen-size"]
+    fn pen_size_goldens_line() {
+        check_pen_sizes("line", || {
+            vexDisplayLineDraw(40, 60, 200, 60);
+            vexDisplayLineDraw(40, 80, 40, 200);
+            vexDisplayLineDraw(80, 80, 200, 200);
+            vexDisplayLineDraw(240, 200, 440, 100);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs captures from a V5 Brain in tests/golden/pen-size"]
+    fn pen_size_goldens_rect() {
+        check_pen_sizes("rect", || {
+            vexDisplayRectDraw(40, 60, 200, 200);
+            vexDisplayRectDraw(240, 60, 249, 69);
+            vexDisplayRectDraw(280, 60, 282, 200);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs captures from a V5 Brain in tests/golden/pen-size"]
+    fn pen_size_goldens_circle() {
+        check_pen_sizes("circle", || {
+            vexDisplayCircleDraw(120, 130, 70);
+            vexDisplayCircleDraw(260, 130, 4);
+            vexDisplayCircleDraw(380, 130, 1);
+        });
+    }
+}
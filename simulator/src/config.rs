@@ -1,13 +1,417 @@
-use std::{collections::HashSet, str::FromStr};
+//! Simulator settings.
+//!
+//! Settings are loaded when the simulator starts from the following sources, with later sources
+//! taking precedence over earlier ones:
+//!
+//! 1. A `roboscope.toml` file in the current directory (or the file named by `--config` or
+//!    `ROBOSCOPE_CONFIG`).
+//! 2. `ROBOSCOPE_*` environment variables, e.g. `ROBOSCOPE_THEME=light`.
+//! 3. Command line arguments, e.g. `--theme light` or `--theme=light`. Arguments which aren't
+//!    settings are ignored, so they can be used by the program. On/off settings like
+//!    `--fullscreen` are enabled by passing them on their own, and can only be given a value with
+//!    `=`, e.g. `--fullscreen=false`.
+//!
+//! Each setting uses the same kebab-case name in the config file and on the command line, and the
+//! equivalent SCREAMING_SNAKE_CASE name in environment variables:
+//!
+//! | Setting            | Value                                                         |
+//! | ------------------ | ------------------------------------------------------------- |
+//! | `debug`            | Comma-separated debug flags (`text-buffer`)                   |
+//! | `fullscreen`       | Hide the program header (`true`/`false`)                      |
+//! | `theme`            | Header and default canvas colors (`dark`, `light`)            |
+//! | `battery-capacity` | Battery capacity when the program starts, in percent          |
+//! | `snapshot-dir`     | Directory for headless display snapshots                      |
+//! | `snapshot-every`   | Save a headless snapshot every N frames (`0` to disable)      |
+//! | `snapshot-on-exit` | Save a headless snapshot when the program exits               |
+//! | `exit-on-stop`     | Shut down the simulator when the program stops                |
+//! | `journal-capacity` | Number of draw calls kept in the journal (`0` to disable)     |
+//! | `robot`            | ID of the simulated robot, which namespaces its IPC services  |
+//! | `provider-timeout` | Disconnect devices after N ms without readings (`0` to never) |
+//! | `ipc-root`         | Directory iceoryx2 keeps its shared files in                  |
+//! | `ipc-domain`       | Only connect to viewers and providers in the same domain      |
+//! | `ipc-buffer-size`  | Samples each IPC subscriber can hold before it's full         |
+//! | `ipc-history-size` | Samples new IPC subscribers receive when they connect         |
+//! | `ipc-max-nodes`    | Processes which can use an IPC service at the same time       |
+//!
+//! The `ipc-*` settings use the same environment variables as the other Roboscope tools (see
+//! [`roboscope_ipc::options`]).
+//!
+//! `ROBOSCOPE_BLESS` is only read from the environment, by golden-image tests (see the `testing`
+//! module): setting it to anything other than `0` replaces the golden images with the current
+//! display instead of comparing them.
 
+use std::{
+    collections::HashSet,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use roboscope_ipc::{DEFAULT_ROBOT, IpcOptions};
 use thiserror::Error;
+use tracing::{debug, warn};
+use vex_sdk::{V5_SIG_OPTIONS_EXIT, V5_SIG_OPTIONS_INDG, V5_SIG_OPTIONS_THDG};
+
+/// The name of the config file which is loaded from the current directory by default.
+pub const CONFIG_FILE_NAME: &str = "roboscope.toml";
+
+/// The prefix of environment variables which change settings.
+const ENV_PREFIX: &str = "ROBOSCOPE_";
 
-#[derive(Debug, PartialEq, Clone, Default)]
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Get the simulator config.
+///
+/// If the config wasn't loaded by [`run_simulator`](crate::run_simulator) (e.g. in tests), it's
+/// loaded from the config file and environment variables the first time this is called.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        Config::load(None).unwrap_or_else(|error| {
+            warn!(%error, "Failed to load simulator config, using the default settings");
+            Config::default()
+        })
+    })
+}
+
+/// Set the simulator config.
+///
+/// This has no effect if the config has already been loaded, since parts of the simulator may
+/// already be using it.
+pub fn init(config: Config) {
+    debug!(?config, "Loaded simulator config");
+
+    if CONFIG.set(config).is_err() {
+        warn!(
+            "The simulator config was used before it was loaded, so some settings may be ignored"
+        );
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Config {
-    debug: HashSet<DebugFlag>,
-    fullscreen: bool,
-    theme: DisplayTheme,
-    battery_capacity: f64,
+    pub debug: HashSet<DebugFlag>,
+    pub fullscreen: bool,
+    pub theme: DisplayTheme,
+    /// Swap the default foreground and background colors of the user canvas. This isn't a
+    /// setting, and is only enabled by the program's code signature.
+    pub invert_colors: bool,
+    pub battery_capacity: f64,
+    pub snapshot_dir: PathBuf,
+    pub snapshot_every: u64,
+    pub snapshot_on_exit: bool,
+    /// Shut down the simulator as soon as the program stops, instead of waiting for the frontend
+    /// to be closed. This is also enabled by the program's code signature.
+    pub exit_on_stop: bool,
+    pub journal_capacity: usize,
+    pub robot: String,
+    /// How long the physics provider can go without sending readings before the devices are
+    /// disconnected, or `None` to keep the last readings forever.
+    pub provider_timeout: Option<Duration>,
+    pub ipc: IpcOptions,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            debug: HashSet::new(),
+            fullscreen: false,
+            theme: DisplayTheme::default(),
+            invert_colors: false,
+            battery_capacity: 100.0,
+            snapshot_dir: PathBuf::from("snapshots"),
+            snapshot_every: 0,
+            snapshot_on_exit: true,
+            exit_on_stop: false,
+            journal_capacity: 0,
+            robot: DEFAULT_ROBOT.to_string(),
+            provider_timeout: Some(Duration::from_millis(500)),
+            ipc: IpcOptions::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from the config file, environment variables and the given command line
+    /// arguments (not including the program name).
+    pub fn load(args: Option<&[String]>) -> Result<Self, ConfigError> {
+        Self::load_with_env(args, |name| env::var(name).ok())
+    }
+
+    /// Load the config, reading environment variables with the given function.
+    fn load_with_env(
+        args: Option<&[String]>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let args = args.map(parse_args).transpose()?.unwrap_or_default();
+        let mut config = Self::default();
+
+        let explicit_path = args
+            .iter()
+            .rev()
+            .find_map(|(key, value)| (*key == ArgKey::Config).then(|| PathBuf::from(value)))
+            .or_else(|| var(&format!("{ENV_PREFIX}CONFIG")).map(PathBuf::from));
+
+        match explicit_path {
+            Some(path) => config.apply_file(&path)?,
+            None if Path::new(CONFIG_FILE_NAME).exists() => {
+                config.apply_file(Path::new(CONFIG_FILE_NAME))?;
+            }
+            None => {}
+        }
+
+        for key in ConfigKey::ALL {
+            if let Some(value) = var(&key.env_var()) {
+                config.set(key, &value)?;
+            }
+        }
+
+        for (key, value) in args {
+            if let ArgKey::Setting(key) = key {
+                config.set(key, &value)?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Apply the options from a program's code signature (`vcodesig`).
+    ///
+    /// Programs with the [`V5_SIG_OPTIONS_THDG`] option are shown using the light theme, since
+    /// they were written to match it, [`V5_SIG_OPTIONS_INDG`] swaps the default colors of the user
+    /// canvas, and [`V5_SIG_OPTIONS_EXIT`] shuts down the simulator when the program stops.
+    pub fn apply_signature_options(&mut self, options: u32) {
+        if options & V5_SIG_OPTIONS_THDG != 0 {
+            self.theme = DisplayTheme::Light;
+        }
+
+        if options & V5_SIG_OPTIONS_INDG != 0 {
+            self.invert_colors = true;
+        }
+
+        if options & V5_SIG_OPTIONS_EXIT != 0 {
+            self.exit_on_stop = true;
+        }
+    }
+
+    /// Returns `true` if the given debug flag is enabled.
+    pub fn debug_enabled(&self, flag: DebugFlag) -> bool {
+        self.debug.contains(&flag)
+    }
+
+    /// Change a setting, parsing its value from a string.
+    pub fn set(&mut self, key: ConfigKey, value: &str) -> Result<(), ConfigError> {
+        let invalid = |expected: &'static str| ConfigError::InvalidValue {
+            key,
+            value: value.to_string(),
+            expected,
+        };
+
+        match key {
+            ConfigKey::Debug => {
+                self.debug = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|flag| !flag.is_empty())
+                    .map(DebugFlag::from_str)
+                    .collect::<Result<_, _>>()?;
+            }
+            ConfigKey::Fullscreen => {
+                self.fullscreen = parse_bool(value).ok_or_else(|| invalid("true or false"))?;
+            }
+            ConfigKey::Theme => self.theme = value.parse()?,
+            ConfigKey::BatteryCapacity => {
+                self.battery_capacity = value
+                    .parse()
+                    .ok()
+                    .filter(|capacity| (0.0..=100.0).contains(capacity))
+                    .ok_or_else(|| invalid("a percentage from 0 to 100"))?;
+            }
+            ConfigKey::SnapshotDir => self.snapshot_dir = PathBuf::from(value),
+            ConfigKey::SnapshotEvery => {
+                self.snapshot_every = value.parse().map_err(|_| invalid("a number of frames"))?;
+            }
+            ConfigKey::SnapshotOnExit => {
+                self.snapshot_on_exit =
+                    parse_bool(value).ok_or_else(|| invalid("true or false"))?;
+            }
+            ConfigKey::ExitOnStop => {
+                self.exit_on_stop = parse_bool(value).ok_or_else(|| invalid("true or false"))?;
+            }
+            ConfigKey::JournalCapacity => {
+                self.journal_capacity = value
+                    .parse()
+                    .map_err(|_| invalid("a number of draw calls"))?;
+            }
+            ConfigKey::Robot => self.robot = value.to_string(),
+            ConfigKey::ProviderTimeout => {
+                let millis: u64 = value.parse().map_err(|_| invalid("a number of milliseconds"))?;
+                self.provider_timeout = (millis != 0).then(|| Duration::from_millis(millis));
+            }
+            ConfigKey::IpcRoot => self.ipc.root_path = Some(PathBuf::from(value)),
+            ConfigKey::IpcDomain => self.ipc.domain = Some(value.to_string()),
+            ConfigKey::IpcBufferSize => {
+                self.ipc.subscriber_buffer_size =
+                    Some(value.parse().map_err(|_| invalid("a number of samples"))?);
+            }
+            ConfigKey::IpcHistorySize => {
+                self.ipc.history_size =
+                    Some(value.parse().map_err(|_| invalid("a number of samples"))?);
+            }
+            ConfigKey::IpcMaxNodes => {
+                self.ipc.max_nodes =
+                    Some(value.parse().map_err(|_| invalid("a number of processes"))?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the settings in a TOML config file.
+    fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        debug!(path = %path.display(), "Loading config file");
+
+        let contents = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let table: toml::Table = contents.parse().map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        for (key, value) in table {
+            let key: ConfigKey = key.parse()?;
+
+            let value = match value {
+                toml::Value::String(string) => string,
+                toml::Value::Integer(int) => int.to_string(),
+                toml::Value::Float(float) => float.to_string(),
+                toml::Value::Boolean(bool) => bool.to_string(),
+                // Lists are only used for debug flags.
+                toml::Value::Array(values) => values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .map_or_else(|| value.to_string(), str::to_string)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => {
+                    return Err(ConfigError::InvalidValue {
+                        key,
+                        value: other.to_string(),
+                        expected: "a string, number, boolean or list",
+                    });
+                }
+            };
+
+            self.set(key, &value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of a setting in [`Config`].
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum ConfigKey {
+    Debug,
+    Fullscreen,
+    Theme,
+    BatteryCapacity,
+    SnapshotDir,
+    SnapshotEvery,
+    SnapshotOnExit,
+    ExitOnStop,
+    JournalCapacity,
+    Robot,
+    ProviderTimeout,
+    IpcRoot,
+    IpcDomain,
+    IpcBufferSize,
+    IpcHistorySize,
+    IpcMaxNodes,
+}
+
+impl ConfigKey {
+    pub const ALL: [Self; 16] = [
+        Self::Debug,
+        Self::Fullscreen,
+        Self::Theme,
+        Self::BatteryCapacity,
+        Self::SnapshotDir,
+        Self::SnapshotEvery,
+        Self::SnapshotOnExit,
+        Self::ExitOnStop,
+        Self::JournalCapacity,
+        Self::Robot,
+        Self::ProviderTimeout,
+        Self::IpcRoot,
+        Self::IpcDomain,
+        Self::IpcBufferSize,
+        Self::IpcHistorySize,
+        Self::IpcMaxNodes,
+    ];
+
+    /// Get the name of the setting as used in config files and command line arguments.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Fullscreen => "fullscreen",
+            Self::Theme => "theme",
+            Self::BatteryCapacity => "battery-capacity",
+            Self::SnapshotDir => "snapshot-dir",
+            Self::SnapshotEvery => "snapshot-every",
+            Self::SnapshotOnExit => "snapshot-on-exit",
+            Self::ExitOnStop => "exit-on-stop",
+            Self::JournalCapacity => "journal-capacity",
+            Self::Robot => "robot",
+            Self::ProviderTimeout => "provider-timeout",
+            Self::IpcRoot => "ipc-root",
+            Self::IpcDomain => "ipc-domain",
+            Self::IpcBufferSize => "ipc-buffer-size",
+            Self::IpcHistorySize => "ipc-history-size",
+            Self::IpcMaxNodes => "ipc-max-nodes",
+        }
+    }
+
+    /// Get the name of the environment variable used to change the setting.
+    pub fn env_var(&self) -> String {
+        format!(
+            "{ENV_PREFIX}{}",
+            self.name().to_uppercase().replace('-', "_")
+        )
+    }
+
+    /// Returns `true` if the setting is enabled on the command line without a value, e.g.
+    /// `--fullscreen`. Flags never take the next argument as their value, since it may belong to
+    /// the program.
+    const fn is_flag(&self) -> bool {
+        matches!(self, Self::Fullscreen | Self::SnapshotOnExit | Self::ExitOnStop)
+    }
+}
+
+impl FromStr for ConfigKey {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|key| key.name() == s)
+            .ok_or_else(|| EnumParseError {
+                name: "config key",
+                value: s.to_string(),
+            })
+    }
+}
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
@@ -57,8 +461,256 @@ impl FromStr for DisplayTheme {
 }
 
 #[derive(Debug, Error)]
-#[error("Unknown {name} {name:?}")]
+#[error("Unknown {name} {value:?}")]
 pub struct EnumParseError {
     name: &'static str,
     value: String,
 }
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    UnknownValue(#[from] EnumParseError),
+
+    #[error("Invalid value {value:?} for {key}, expected {expected}")]
+    InvalidValue {
+        key: ConfigKey,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[error("Missing value for --{0}")]
+    MissingValue(&'static str),
+
+    #[error("Failed to read config file {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to parse config file {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+}
+
+/// A command line option recognized by the simulator.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ArgKey {
+    /// `--config`, the path of the config file.
+    Config,
+    Setting(ConfigKey),
+}
+
+/// Split command line arguments into options and their values.
+///
+/// Arguments which aren't simulator options are skipped, since they may belong to the program.
+fn parse_args(args: &[String]) -> Result<Vec<(ArgKey, String)>, ConfigError> {
+    let mut options = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--") else {
+            continue;
+        };
+
+        let (name, inline_value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (option, None),
+        };
+
+        let key = match name {
+            "config" => ArgKey::Config,
+            _ => match name.parse() {
+                Ok(key) => ArgKey::Setting(key),
+                Err(_) => {
+                    debug!(%arg, "Skipping unknown command line argument");
+                    continue;
+                }
+            },
+        };
+        let is_flag = matches!(key, ArgKey::Setting(key) if key.is_flag());
+
+        let value = match inline_value {
+            Some(value) => value,
+            None if is_flag => "true".to_string(),
+            None => args
+                .next()
+                .cloned()
+                .ok_or(ConfigError::MissingValue(match key {
+                    ArgKey::Config => "config",
+                    ArgKey::Setting(key) => key.name(),
+                }))?,
+        };
+
+        options.push((key, value));
+    }
+
+    Ok(options)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// A config file in the temporary directory, which is removed when it's dropped.
+    struct TempConfig(PathBuf);
+
+    impl TempConfig {
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Write a config file with the given contents to a unique temporary path.
+    fn config_file(name: &str, contents: &str) -> TempConfig {
+        let path = env::temp_dir().join(format!("roboscope-{}-{name}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        TempConfig(path)
+    }
+
+    fn load(args: &[String], vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::load_with_env(Some(args), |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn later_sources_take_precedence() {
+        let file = config_file(
+            "precedence",
+            "theme = \"light\"\nbattery-capacity = 60\nsnapshot-every = 5\n",
+        );
+        let path = file.path();
+
+        let config = load(&args(&["--config", path]), &[]).unwrap();
+        assert_eq!(config.theme, DisplayTheme::Light);
+        assert_eq!(config.battery_capacity, 60.0);
+        assert_eq!(config.snapshot_every, 5);
+
+        let vars = [
+            ("ROBOSCOPE_BATTERY_CAPACITY", "40"),
+            ("ROBOSCOPE_SNAPSHOT_EVERY", "10"),
+        ];
+        let config = load(&args(&["--config", path]), &vars).unwrap();
+        assert_eq!(config.theme, DisplayTheme::Light);
+        assert_eq!(config.battery_capacity, 40.0);
+        assert_eq!(config.snapshot_every, 10);
+
+        let config = load(&args(&["--config", path, "--snapshot-every=20"]), &vars).unwrap();
+        assert_eq!(config.theme, DisplayTheme::Light);
+        assert_eq!(config.battery_capacity, 40.0);
+        assert_eq!(config.snapshot_every, 20);
+    }
+
+    #[test]
+    fn config_path_from_env() {
+        let file = config_file("env-path", "theme = \"light\"\n");
+        let config = load(&[], &[("ROBOSCOPE_CONFIG", file.path())]).unwrap();
+        assert_eq!(config.theme, DisplayTheme::Light);
+    }
+
+    #[test]
+    fn skips_program_arguments() {
+        let file = config_file("empty", "");
+        let config = load(
+            &args(&[
+                "input.txt",
+                "--config",
+                file.path(),
+                "--verbose",
+                "--fullscreen",
+                "--speed=3",
+                "--theme",
+                "light",
+            ]),
+            &[],
+        )
+        .unwrap();
+
+        assert!(config.fullscreen);
+        assert_eq!(config.theme, DisplayTheme::Light);
+    }
+
+    #[test]
+    fn flags_dont_take_program_arguments() {
+        let options =
+            parse_args(&args(&["--fullscreen", "input.txt", "--exit-on-stop", "false"])).unwrap();
+        assert_eq!(
+            options,
+            [
+                (ArgKey::Setting(ConfigKey::Fullscreen), "true".to_string()),
+                (ArgKey::Setting(ConfigKey::ExitOnStop), "true".to_string()),
+            ]
+        );
+
+        let config = load(&args(&["--fullscreen", "input.txt"]), &[]).unwrap();
+        assert!(config.fullscreen);
+
+        let config = load(&args(&["--fullscreen=false", "input.txt"]), &[]).unwrap();
+        assert!(!config.fullscreen);
+    }
+
+    #[test]
+    fn missing_value() {
+        let error = parse_args(&args(&["--theme"])).unwrap_err();
+        assert_eq!(error.to_string(), "Missing value for --theme");
+    }
+
+    #[test]
+    fn enum_parse_errors() {
+        assert_eq!(
+            "purple".parse::<DisplayTheme>().unwrap_err().to_string(),
+            "Unknown theme \"purple\""
+        );
+        assert_eq!(
+            "everything".parse::<DebugFlag>().unwrap_err().to_string(),
+            "Unknown debug flag \"everything\""
+        );
+        assert_eq!(
+            "colour".parse::<ConfigKey>().unwrap_err().to_string(),
+            "Unknown config key \"colour\""
+        );
+
+        let file = config_file("unknown-key", "colour = \"red\"\n");
+        let error = load(&args(&["--config", file.path()]), &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown config key \"colour\"");
+
+        let mut config = Config::default();
+        let error = config.set(ConfigKey::Debug, "text-buffer, everything").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown debug flag \"everything\"");
+    }
+
+    #[test]
+    fn config_keys_round_trip() {
+        for key in ConfigKey::ALL {
+            assert_eq!(key.name().parse::<ConfigKey>().unwrap(), key);
+        }
+        assert_eq!(ConfigKey::IpcBufferSize.env_var(), "ROBOSCOPE_IPC_BUFFER_SIZE");
+    }
+}
@@ -8,54 +8,147 @@ use std::{
         Arc,
         atomic::{AtomicU32, Ordering},
     },
-    thread,
+    thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
 use derive_more::{AsRef, From, TryInto};
 use parking_lot::{Mutex, MutexGuard};
 use roboscope_ipc::{
-    DeviceReadings, DeviceSnapshot, PHYSICS_UPDATE_PERIOD, SMART_DEVICES_COUNT, Sample, SimServices, Subscriber
+    BatteryReading, DeviceReadings, DeviceSnapshot, MotorSnapshot, PHYSICS_UPDATE_PERIOD,
+    Listener, SMART_DEVICES_COUNT, Sample, SimServices, Subscriber,
 };
-use tracing::{debug, trace};
+use tracing::{debug, error, info, trace, warn};
 use vex_sdk::{V5_DeviceT, V5_DeviceType};
 
-use crate::sdk::vexSystemTimeGet;
+use crate::{battery::BATTERY, config, lifecycle, sdk::vexSystemTimeGet};
 
-pub fn start_device_handler(ipc: Arc<SimServices>) {
+/// Start receiving device readings from the physics provider, until the simulator exits.
+pub fn start_device_handler(ipc: Arc<SimServices>) -> JoinHandle<()> {
     thread::Builder::new()
         .name("Sim Device Handler".into())
         .spawn(move || {
             debug!("Connecting to physics provider");
-            let dev_handler = DeviceHandler::new(ipc.clone()).expect("created device handler");
-
-            while ipc.node.wait(PHYSICS_UPDATE_PERIOD).is_ok() {
-                dev_handler.update().expect("device update OK");
+            let mut dev_handler = match DeviceHandler::new(ipc.clone()) {
+                Ok(dev_handler) => dev_handler,
+                Err(error) => {
+                    error!(%error, "Failed to set up device readings, devices won't connect");
+                    return;
+                }
+            };
+
+            // Readings are handled as soon as they're published. The timeout makes sure that the
+            // handler still notices when the provider stops sending them, or the simulator exits.
+            while !lifecycle::exit_requested() {
+                match ipc.wait_for_data(&dev_handler.listener, PHYSICS_UPDATE_PERIOD) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(error) => {
+                        error!(%error, "Failed to wait for device readings, disconnecting devices");
+                        DEVICES.disconnect();
+                        break;
+                    }
+                }
+
+                // Failing to receive one batch of readings isn't fatal, the next one is tried on
+                // the following iteration.
+                if let Err(error) = dev_handler.update() {
+                    warn!(%error, "Failed to update devices");
+                }
             }
         })
-        .unwrap();
+        .unwrap()
 }
 
 struct DeviceHandler {
     readings: Subscriber<DeviceReadings>,
+    /// Notified when the physics provider publishes new readings.
+    listener: Listener,
+    connection: ProviderConnection,
 }
 
 impl DeviceHandler {
     pub fn new(ipc: Arc<SimServices>) -> anyhow::Result<Self> {
         let captures = ipc.device_readings()?.subscriber_builder().create()?;
+        let listener = ipc.device_reading_events()?.listener_builder().create()?;
 
-        Ok(Self { readings: captures })
+        Ok(Self {
+            readings: captures,
+            listener,
+            connection: ProviderConnection::default(),
+        })
     }
 
-    pub fn update(&self) -> anyhow::Result<()> {
-        if let Some(sample) = self.readings.receive()? {
-            DEVICES.queue_sample(sample);
+    /// Queue the newest readings, or disconnect every device if the physics provider has stopped
+    /// sending them.
+    pub fn update(&mut self) -> anyhow::Result<()> {
+        let received = DEVICES.queue_newest(&self.readings)?;
+        let timeout = config::get().provider_timeout;
+
+        match self.connection.update(received, Instant::now(), timeout) {
+            ConnectionChange::Connected => info!("Physics provider connected"),
+            ConnectionChange::TimedOut(elapsed) => {
+                warn!(
+                    "Physics provider hasn't sent readings for {elapsed:?}, disconnecting devices"
+                );
+                DEVICES.disconnect();
+            }
+            ConnectionChange::None => {}
         }
 
         Ok(())
     }
 }
 
+/// Tracks whether the physics provider is still sending readings.
+#[derive(Debug, Default)]
+struct ProviderConnection {
+    /// When the latest readings were received, while the physics provider is connected.
+    last_received: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionChange {
+    None,
+    /// The provider sent its first readings, or its first readings since it timed out.
+    Connected,
+    /// The provider hasn't sent readings for longer than the timeout, which is how long it's been.
+    TimedOut(Duration),
+}
+
+impl ProviderConnection {
+    /// Update the connection state at `now`, given whether new readings were `received`.
+    ///
+    /// A `timeout` of `None` means the provider never times out.
+    fn update(
+        &mut self,
+        received: bool,
+        now: Instant,
+        timeout: Option<Duration>,
+    ) -> ConnectionChange {
+        if received {
+            let was_connected = self.last_received.replace(now).is_some();
+            return if was_connected {
+                ConnectionChange::None
+            } else {
+                ConnectionChange::Connected
+            };
+        }
+
+        let Some(last_received) = self.last_received else {
+            return ConnectionChange::None;
+        };
+
+        let elapsed = now.saturating_duration_since(last_received);
+        if timeout.is_some_and(|timeout| elapsed > timeout) {
+            self.last_received = None;
+            return ConnectionChange::TimedOut(elapsed);
+        }
+
+        ConnectionChange::None
+    }
+}
+
 struct QueuedSample {
     inner: Sample<DeviceReadings>,
     timestamp: u32,
@@ -85,15 +178,29 @@ impl Devices {
         }
     }
 
-    pub fn queue_sample(&self, sample: Sample<DeviceReadings>) {
-        trace!(?sample, "Queueing new device sample");
-        *self.queued_sample.lock() = Some(QueuedSample {
-            inner: sample,
-            timestamp: vexSystemTimeGet(),
-        });
+    /// Receive every pending sample and queue the newest one, returning `true` if any were
+    /// received.
+    ///
+    /// The queued sample counts towards the subscriber's borrowed samples until it's committed, so
+    /// each sample replaces it as soon as it's received, which keeps at most two borrowed at once.
+    pub fn queue_newest(&self, subscriber: &Subscriber<DeviceReadings>) -> anyhow::Result<bool> {
+        let mut queued_sample = self.queued_sample.lock();
+        let mut received = false;
+
+        while let Some(sample) = subscriber.receive()? {
+            trace!(?sample, "Queueing new device sample");
+            *queued_sample = Some(QueuedSample {
+                inner: sample,
+                timestamp: vexSystemTimeGet(),
+            });
+            received = true;
+        }
+
+        Ok(received)
     }
 
-    /// Copy the latest device readings (if any are available) from shared memory.
+    /// Copy the latest device readings (if any are available) from shared memory, then drain
+    /// the battery by the current the motors are drawing.
     pub fn update_readings(&self) {
         trace!("Committing queued sample");
 
@@ -104,6 +211,41 @@ impl Devices {
                     timestamp: sample.timestamp,
                 };
             }
+
+            BATTERY.lock().set_reading(sample.inner.1);
+        }
+
+        let current = self.motor_current();
+        BATTERY.lock().update(current);
+    }
+
+    /// Get the total current drawn by every motor, in milliamps.
+    pub fn motor_current(&self) -> i32 {
+        self.smart_devices
+            .iter()
+            .filter_map(|device| {
+                let device = device.0.lock();
+                device.readings::<MotorSnapshot>().map(|motor| motor.current.max(0))
+            })
+            .sum()
+    }
+
+    /// Disconnect every device and stop using the physics provider's battery reading, like
+    /// unplugging every cable. Devices reconnect with the next sample from the physics provider.
+    pub fn disconnect(&self) {
+        // Holding the queue's lock stops a sample which is being committed from overwriting this.
+        let mut queued_sample = self.queued_sample.lock();
+        *queued_sample = None;
+
+        self.reset();
+        BATTERY.lock().set_reading(BatteryReading::Simulated);
+    }
+
+    /// Forget the readings of every device, e.g. because the program was restarted. Readings are
+    /// received again with the next sample from the physics provider.
+    pub fn reset(&self) {
+        for device in &self.smart_devices {
+            *device.0.lock() = V5DeviceData::new();
         }
     }
 
@@ -175,6 +317,50 @@ impl V5Device {
             DeviceSnapshot::Empty => V5_DeviceType::kDeviceTypeNoSensor,
             DeviceSnapshot::Generic(_) => V5_DeviceType::kDeviceTypeGenericSensor,
             DeviceSnapshot::Distance(_) => V5_DeviceType::kDeviceTypeDistanceSensor,
+            DeviceSnapshot::Motor(_) => V5_DeviceType::kDeviceTypeMotorSensor,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_millis(100));
+
+    #[test]
+    fn provider_times_out_and_reconnects() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut connection = ProviderConnection::default();
+
+        assert_eq!(connection.update(false, at(0), TIMEOUT), ConnectionChange::None);
+        assert_eq!(connection.update(true, at(10), TIMEOUT), ConnectionChange::Connected);
+        assert_eq!(connection.update(true, at(20), TIMEOUT), ConnectionChange::None);
+
+        // Going quiet for less than the timeout is fine.
+        assert_eq!(connection.update(false, at(120), TIMEOUT), ConnectionChange::None);
+        assert_eq!(
+            connection.update(false, at(121), TIMEOUT),
+            ConnectionChange::TimedOut(Duration::from_millis(101))
+        );
+
+        // The timeout is only reported once.
+        assert_eq!(connection.update(false, at(500), TIMEOUT), ConnectionChange::None);
+
+        assert_eq!(connection.update(true, at(510), TIMEOUT), ConnectionChange::Connected);
+        assert_eq!(connection.update(false, at(600), TIMEOUT), ConnectionChange::None);
+    }
+
+    #[test]
+    fn provider_without_timeout_stays_connected() {
+        let start = Instant::now();
+        let mut connection = ProviderConnection::default();
+
+        assert_eq!(connection.update(true, start, None), ConnectionChange::Connected);
+        assert_eq!(
+            connection.update(false, start + Duration::from_secs(3600), None),
+            ConnectionChange::None
+        );
+    }
+}
@@ -1,11 +1,22 @@
-use std::{mem, num::NonZeroU32, rc::Rc, sync::LazyLock, time::Instant};
+use std::{
+    mem,
+    num::NonZeroU32,
+    path::Path,
+    rc::Rc,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
 
 use parking_lot::{Condvar, Mutex};
+use roboscope_ipc::screenshot;
 use tracing::{debug, trace};
 use vex_sdk::{V5_TouchEvent, V5_TouchStatus};
 
 use crate::{
-    canvas::{BUFSZ, CANVAS, Canvas, HEIGHT, Point, Rect, WIDTH, img::SimImage},
+    battery::{BATTERY, LOW_CAPACITY},
+    canvas::{BUFSZ, CANVAS, Canvas, HEIGHT, Point, Rect, ThemeColors, WIDTH, img::SimImage},
+    config::{self, DisplayTheme},
+    lifecycle::PANIC_EXIT_CODE,
 };
 
 /// The shared V5 display instance.
@@ -34,6 +45,14 @@ pub struct SimDisplay {
     /// canvas mask to the full contents of the window.
     pub header_hidden: bool,
 
+    /// The theme used for the program header and the parts of the display which the user canvas
+    /// hasn't been drawn on yet.
+    theme: DisplayTheme,
+
+    /// The exit code of the program once it has ended, after which the user canvas is no longer
+    /// shown.
+    program_ended: Option<i32>,
+
     /// Indicates whether redraws should automatically render the user canvas without calls to
     /// [`vexDisplayRender`](crate::sdk::vexDisplayRender).
     pub autorender: bool,
@@ -42,6 +61,14 @@ pub struct SimDisplay {
     ///
     /// This is effectively drawn on a separate layer from the default user canvas.
     system_canvas: Option<Box<Canvas>>,
+    /// The contents of the most recently drawn program header, used to skip redrawing it when
+    /// nothing has changed.
+    header_state: Option<HeaderState>,
+    /// The mask most recently used to copy the user canvas onto the display.
+    user_mask: Rect,
+    /// The region of the display which has changed since the last call to
+    /// [`SimDisplay::take_dirty`].
+    dirty: Option<Rect>,
     program_start: Instant,
     program_display_name: String,
 
@@ -55,12 +82,19 @@ impl SimDisplay {
     pub fn new() -> Self {
         debug!("Initializing shared display");
 
+        let config = config::get();
+
         Self {
-            buffer: [0; _],
-            header_hidden: false,
+            buffer: [ThemeColors::from_config(config).background; _],
+            header_hidden: config.fullscreen,
+            theme: config.theme,
+            program_ended: None,
             autorender: true,
             mouse_coords: Point::new(0, 0),
             system_canvas: Some(Canvas::new().into()),
+            header_state: None,
+            user_mask: Rect::USER_CLIP,
+            dirty: None,
             program_display_name: String::new(),
             program_start: Instant::now(),
             mouse_down: false,
@@ -79,11 +113,13 @@ impl SimDisplay {
     pub fn render(&mut self) {
         trace!("Rendering frame");
         if self.autorender {
-            let canvas = CANVAS.lock();
-            self.render_user_canvas(&canvas);
+            let mut canvas = CANVAS.lock();
+            self.render_user_canvas(&mut canvas);
         }
 
-        if !self.header_hidden {
+        if self.header_hidden {
+            self.header_state = None;
+        } else {
             self.render_header();
         }
     }
@@ -92,31 +128,136 @@ impl SimDisplay {
     pub fn blit_rect(&mut self, source: &[u32; BUFSZ], mut mask: Rect) {
         mask.clip_to(Rect::FULL_CLIP);
 
-        for pixel in mask.pixels() {
-            let idx = (pixel.y * WIDTH as i32 + pixel.x) as usize;
-            self.buffer[idx] = source[idx];
+        if mask.is_empty() {
+            return;
+        }
+
+        for y in mask.top()..mask.1.y {
+            let row_start = (y * WIDTH as i32) as usize;
+            let row = (row_start + mask.left() as usize)..(row_start + mask.1.x as usize);
+            self.buffer[row.clone()].copy_from_slice(&source[row]);
         }
+
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(mask),
+            None => mask,
+        });
     }
 
-    /// Copy the given canvas onto the display using the correct mask for the global user canvas.
-    pub fn render_user_canvas(&mut self, canvas: &Canvas) {
+    /// Copy the parts of the given canvas which have changed onto the display using the correct
+    /// mask for the global user canvas.
+    pub fn render_user_canvas(&mut self, canvas: &mut Canvas) {
+        if self.program_ended.is_some() {
+            return;
+        }
+
         let mask = if self.header_hidden {
             Rect::FULL_CLIP
         } else {
             Rect::USER_CLIP
         };
-        self.blit_rect(canvas.buffer(), mask);
+
+        let dirty = canvas.take_dirty();
+
+        // Changes that were hidden by the old mask could be visible now, so copy everything.
+        if mem::replace(&mut self.user_mask, mask) != mask {
+            self.blit_rect(canvas.buffer(), mask);
+            return;
+        }
+
+        if let Some(changed) = dirty.and_then(|dirty| dirty.intersection(mask)) {
+            self.blit_rect(canvas.buffer(), changed);
+        }
+    }
+
+    /// Get the region of the display which has changed since the last time this function was
+    /// called, resetting it to be empty.
+    ///
+    /// Returns `None` if the display hasn't changed.
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Reset the display to how it was when the program started, keeping the program name.
+    ///
+    /// This resets the program timer and touch state, and redraws the whole display.
+    pub fn restart(&mut self) {
+        let program_display_name = mem::take(&mut self.program_display_name);
+        *self = Self::new();
+        self.program_display_name = program_display_name;
+        self.dirty = Some(Rect::FULL_CLIP);
     }
 
     /// Set the program name shown in the display header.
     pub fn set_program_name(&mut self, name: &str) {
-        let display_name = if name.len() > 15 {
-            format!("{}...", &name[0..=12])
-        } else {
-            name.to_string()
+        // Truncate by characters, since slicing in the middle of one would panic.
+        let display_name = match name.char_indices().nth(13) {
+            Some((end, _)) if name.chars().count() > 15 => format!("{}...", &name[..end]),
+            _ => name.to_string(),
         };
 
         self.program_display_name = display_name;
+        self.header_state = None;
+    }
+
+    /// Save the contents of the display as a PNG image.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        screenshot::save_png(&self.buffer, path)?;
+        Ok(())
+    }
+
+    /// Save the current contents of the display as a PNG image, scaled to the given size.
+    ///
+    /// Pixels are scaled with nearest-neighbor sampling, so the image looks the same as the
+    /// display does in a window of that size.
+    pub fn save_scaled_png(
+        &self,
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        screenshot::save_scaled_png(&self.buffer, width, height, path)?;
+        Ok(())
+    }
+
+    /// Stop showing the user canvas and show a message saying that the program has ended instead,
+    /// like VEXos does when a program stops.
+    ///
+    /// If the program panicked, the error screen drawn on the user canvas is left visible instead.
+    pub fn show_program_ended(&mut self, exit_code: i32) {
+        if self.program_ended.replace(exit_code).is_some() || exit_code == PANIC_EXIT_CODE {
+            return;
+        }
+
+        let colors = ThemeColors::for_theme(self.theme);
+        let mut canvas = self.system_canvas.take().unwrap();
+
+        let dialog = Rect::sized(90, 112, 300, 80);
+        canvas.state.fg_color = colors.header;
+        canvas.fill_rect(dialog);
+        canvas.state.fg_color = colors.header_text;
+        canvas.draw_rect(dialog);
+
+        canvas.state.set_named_font("proportional");
+        canvas.state.font_scale = (2, 5);
+        let title = "Program Ended";
+        let title_x = dialog.left() + (300 - canvas.measure_string_width(title)) / 2;
+        canvas.draw_string(Point::new(title_x, dialog.top() + 8), title, false);
+
+        if exit_code != 0 {
+            canvas.state.font_scale = (1, 3);
+            let detail = format!("Exit code {exit_code}");
+            let detail_x = dialog.left() + (300 - canvas.measure_string_width(&detail)) / 2;
+            canvas.draw_string(Point::new(detail_x, dialog.top() + 46), &detail, false);
+        }
+
+        self.blit_rect(canvas.buffer(), dialog);
+        self.system_canvas = Some(canvas);
+    }
+
+    /// Get the amount of time since the program started.
+    pub fn program_time(&self) -> Duration {
+        self.program_start.elapsed()
     }
 
     /// Get the current touch status of the display.
@@ -140,45 +281,71 @@ impl SimDisplay {
     }
 
     /// Draw the program header on the header canvas.
+    ///
+    /// The header is only redrawn when its contents have changed since the last time it was drawn.
     fn render_header(&mut self) {
+        let elapsed = self.program_time().as_secs();
+        let minutes = elapsed / 60;
+        let seconds = elapsed % 60;
+        let elapsed_time = format!("{minutes}:{seconds:02}");
+
+        let battery = BATTERY.lock().snapshot().capacity.clamp(0.0, 100.0).round() as i32;
+
+        let state = HeaderState {
+            elapsed_time,
+            battery,
+        };
+        if self.header_state.as_ref() == Some(&state) {
+            return;
+        }
+
+        let colors = ThemeColors::for_theme(self.theme);
         let mut canvas = self.system_canvas.take().unwrap();
 
-        canvas.state.fg_color = 0x00_99_CC;
+        canvas.state.fg_color = colors.header;
         canvas.fill_rect(Rect::HEADER_CLIP);
 
-        canvas.state.fg_color = 0x00_00_00;
+        canvas.state.fg_color = colors.header_text;
 
         canvas.state.set_named_font("proportional");
         canvas.state.font_scale = (2, 5);
         canvas.draw_string(Point::new(8, 0), &self.program_display_name, false);
 
-        let elapsed = self.program_start.elapsed().as_secs();
-        let minutes = elapsed / 60;
-        let seconds = elapsed % 60;
-        let elapsed_time = format!("{minutes}:{seconds:02}");
-
         canvas.state.set_named_font("NotoMono_39pt");
         canvas.state.font_scale = (3, 5);
-        canvas.draw_string(Point::new(246, 3), &elapsed_time, false);
+        canvas.draw_string(Point::new(246, 3), &state.elapsed_time, false);
 
         let device = &*DEVICE_IMAGE;
         let device_coords = Point::new(WIDTH as i32 - device.width() - 4, -1);
         DEVICE_IMAGE.draw(&mut canvas, device_coords);
 
-        // TODO: dynamically set using device snapshots, yellow when <= 30%
         let battery = Rect::sized(452, 23, 13, 9);
-        canvas.state.fg_color = 0x93_C8_3F;
-        canvas.fill_rect(battery);
+        canvas.state.fg_color = if f64::from(state.battery) <= LOW_CAPACITY {
+            colors.battery_low
+        } else {
+            colors.battery
+        };
+        let fill_width = ((13 * state.battery + 99) / 100).max(1);
+        canvas.fill_rect(Rect::sized(battery.left(), battery.top(), fill_width, 9));
 
-        canvas.state.fg_color = 0x00_00_00;
+        canvas.state.fg_color = colors.header_text;
         canvas.draw_rect(battery);
         canvas.fill_rect(Rect::sized(battery.left() + 13, battery.top() + 3, 2, 3));
 
         self.blit_rect(canvas.buffer(), Rect::HEADER_CLIP);
         self.system_canvas = Some(canvas);
+        self.header_state = Some(state);
     }
 }
 
+/// Everything displayed in the program header which can change while the program is running.
+#[derive(Debug, PartialEq)]
+struct HeaderState {
+    elapsed_time: String,
+    /// The remaining battery capacity, in percent.
+    battery: i32,
+}
+
 /// Access to the underlying pixel buffer.
 impl AsRef<[u32]> for SimDisplay {
     fn as_ref(&self) -> &[u32] {
@@ -0,0 +1,250 @@
+//! VEXos-style error screen shown when the program panics.
+//!
+//! On a brain, vexide catches panics and draws the panic message in a box on the display so the
+//! driver can see what went wrong. The simulator does the same: a panic hook records the message,
+//! location and a short backtrace, and once the program's entrypoint has unwound, the report is
+//! drawn onto the user canvas and rendered.
+
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    fmt::Write,
+    panic::{self, PanicHookInfo},
+    sync::Once,
+};
+
+use crate::{
+    canvas::{CANVAS, Canvas, HEADER_HEIGHT, HEIGHT, Point, Rect, WIDTH},
+    display::DISPLAY,
+    lifecycle,
+};
+
+/// The maximum number of backtrace frames shown on the error screen.
+const MAX_BACKTRACE_FRAMES: usize = 6;
+
+const BACKGROUND_COLOR: u32 = 0x8B_00_00;
+const TEXT_COLOR: u32 = 0xFF_FF_FF;
+const MARGIN: i32 = 8;
+
+thread_local! {
+    /// The most recent panic on this thread.
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// A description of a panic which can be drawn on the display.
+#[derive(Debug, Clone)]
+pub struct PanicReport {
+    /// The panic message.
+    pub message: String,
+    /// Where the panic happened, as `file:line:column`.
+    pub location: Option<String>,
+    /// The names of the functions which were running when the panic happened, innermost first,
+    /// leaving out the panic machinery and the standard library.
+    pub backtrace: Vec<String>,
+}
+
+impl PanicReport {
+    fn from_hook_info(info: &PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+
+        // Capturing a backtrace is slow, and only panics in the program are shown on the display.
+        let backtrace = if lifecycle::is_program_thread() {
+            summarize_backtrace(&Backtrace::force_capture().to_string())
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            message,
+            location: info.location().map(|location| location.to_string()),
+            backtrace,
+        }
+    }
+}
+
+/// Install a panic hook which records panics so they can be shown on the error screen.
+///
+/// The previous hook still runs, so panics are printed as usual.
+pub fn install_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let report = PanicReport::from_hook_info(info);
+            LAST_PANIC.with_borrow_mut(|last| *last = Some(report));
+            previous(info);
+        }));
+    });
+}
+
+/// Use the given report for the panic the current thread is about to unwind with, instead of one
+/// recorded by the panic hook.
+///
+/// This is for hosts which run the program somewhere the hook can't see its panics (e.g. in a
+/// dynamic library with its own copy of the standard library). They should call this, then
+/// unwind with [`panic::resume_unwind`], which doesn't run the hook, so that the error screen
+/// shows the program's panic rather than the host's.
+pub fn report_panic(report: PanicReport) {
+    LAST_PANIC.with_borrow_mut(|last| *last = Some(report));
+}
+
+/// Take the report of the most recent panic on the current thread.
+pub fn take_report() -> Option<PanicReport> {
+    LAST_PANIC.take()
+}
+
+/// Draw the report on the user canvas and render it to the display straight away.
+pub fn show(report: &PanicReport) {
+    // Lock order: the display must be locked before the canvas.
+    let mut display = DISPLAY.lock();
+    let mut canvas = CANVAS.lock();
+
+    draw(&mut canvas, report);
+    display.render_user_canvas(&mut canvas);
+}
+
+fn draw(canvas: &mut Canvas, report: &PanicReport) {
+    canvas.state.set_clip_region(Rect::FULL_CLIP);
+    canvas.state.fg_color = BACKGROUND_COLOR;
+    canvas.fill_rect(Rect::sized(0, HEADER_HEIGHT, WIDTH as i32, HEIGHT as i32 - HEADER_HEIGHT));
+
+    canvas.state.fg_color = TEXT_COLOR;
+    canvas.state.bg_color = BACKGROUND_COLOR;
+    canvas.draw_rect(Rect::sized(
+        MARGIN / 2,
+        HEADER_HEIGHT + MARGIN / 2,
+        WIDTH as i32 - MARGIN,
+        HEIGHT as i32 - HEADER_HEIGHT - MARGIN,
+    ));
+
+    canvas.state.set_named_font("monospace");
+    canvas.state.font_scale = (1, 3);
+    let line_height = canvas.measure_string_height("");
+    let max_columns = ((WIDTH as i32 - MARGIN * 2) / canvas.measure_string_width("M")).max(1);
+
+    let mut text = String::from("panicked");
+    if let Some(location) = &report.location {
+        _ = write!(text, " at {location}");
+    }
+    text.push_str(":\n");
+    text.push_str(&report.message);
+
+    if !report.backtrace.is_empty() {
+        text.push_str("\n\nstack backtrace:");
+        for (index, function) in report.backtrace.iter().enumerate() {
+            _ = write!(text, "\n{index}: {function}");
+        }
+    }
+
+    let mut y = HEADER_HEIGHT + MARGIN;
+    for line in wrap_lines(&text, max_columns as usize) {
+        if y + line_height > HEIGHT as i32 - MARGIN {
+            break;
+        }
+
+        canvas.draw_string(Point::new(MARGIN, y), &line, false);
+        y += line_height;
+    }
+}
+
+/// Split text into lines no longer than the given number of characters.
+fn wrap_lines(text: &str, max_columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+            continue;
+        }
+
+        lines.extend(chars.chunks(max_columns).map(|chunk| chunk.iter().collect()));
+    }
+
+    lines
+}
+
+/// Get the names of the interesting functions in a formatted backtrace.
+fn summarize_backtrace(backtrace: &str) -> Vec<String> {
+    const HIDDEN_PREFIXES: &[&str] = &[
+        "std::",
+        "core::",
+        "alloc::",
+        "<alloc::",
+        "<core::",
+        "<std::",
+        "rust_begin_unwind",
+        "__rust",
+        "vex_sdk_desktop::",
+    ];
+
+    backtrace
+        .lines()
+        .filter_map(|line| {
+            // Frames look like `  12: crate::module::function`, followed by a line with their
+            // source location.
+            let (index, function) = line.trim().split_once(": ")?;
+            index.parse::<usize>().ok()?;
+            Some(function.to_string())
+        })
+        .filter(|function| !HIDDEN_PREFIXES.iter().any(|prefix| function.starts_with(prefix)))
+        .take(MAX_BACKTRACE_FRAMES)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_long_words() {
+        assert_eq!(wrap_lines(&"a".repeat(25), 10), ["aaaaaaaaaa", "aaaaaaaaaa", "aaaaa"]);
+        assert_eq!(wrap_lines("short", 10), ["short"]);
+
+        // Columns are counted in characters rather than bytes.
+        assert_eq!(wrap_lines("ééé", 2), ["éé", "é"]);
+    }
+
+    #[test]
+    fn wrap_empty_lines() {
+        assert!(wrap_lines("", 10).is_empty());
+        assert_eq!(wrap_lines("\n", 10), [""]);
+        assert_eq!(wrap_lines("a\n\nb", 10), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn backtrace_hides_runtime_frames() {
+        let backtrace = "\
+   0: std::backtrace_rs::backtrace::libunwind::trace
+             at /rustc/library/std/src/../../backtrace/src/backtrace/libunwind.rs:117:9
+   1: std::backtrace::Backtrace::force_capture
+   2: vex_sdk_desktop::error_screen::PanicReport::capture
+   3: core::panicking::panic_fmt
+   4: robot::autonomous
+             at ./src/main.rs:10:5
+   5: <alloc::boxed::Box<F,A> as core::ops::function::FnOnce<Args>>::call_once
+   6: robot::main
+   7: __rust_begin_short_backtrace
+";
+
+        assert_eq!(summarize_backtrace(backtrace), ["robot::autonomous", "robot::main"]);
+        assert!(summarize_backtrace("").is_empty());
+    }
+
+    #[test]
+    fn backtrace_is_truncated() {
+        let backtrace: String = (0..20)
+            .map(|index| format!("  {index}: robot::function_{index}\n"))
+            .collect();
+
+        let summary = summarize_backtrace(&backtrace);
+        assert_eq!(summary.len(), MAX_BACKTRACE_FRAMES);
+        assert_eq!(summary[0], "robot::function_0");
+    }
+}
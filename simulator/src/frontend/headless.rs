@@ -0,0 +1,112 @@
+//! Display renderer implementation which runs without a window or IPC and saves frames to PNG
+//! files.
+//!
+//! The following [config](crate::config) settings control when frames are saved:
+//!
+//! - `snapshot-dir`: the directory snapshots are written to (default: `snapshots`)
+//! - `snapshot-every`: save a snapshot every N frames (default: never)
+//! - `snapshot-on-exit`: save a snapshot when the simulator exits (default: `true`)
+//!
+//! The simulator keeps running after the program stops, until it's interrupted or the program
+//! asks to exit. CI jobs usually want to set `exit-on-stop` so that it exits with the program.
+//!
+//! Snapshots can also be requested by the program itself using [`request_snapshot`].
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Instant,
+};
+
+use anyhow::Context;
+use roboscope_ipc::DISPLAY_UPDATE_PERIOD;
+use tracing::{debug, error, info};
+
+use crate::{
+    config,
+    display::{DISPLAY, FRAME_FINISHED, SimDisplay},
+    lifecycle,
+};
+
+/// Set when a snapshot of the next frame should be saved.
+static SNAPSHOT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Save a snapshot of the display after the next frame is rendered.
+pub fn request_snapshot() {
+    SNAPSHOT_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn start(name: &str, entrypoint: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
+    DISPLAY.lock().set_program_name(name);
+
+    let snapshots = SnapshotOptions::from_config()?;
+    debug!(?snapshots, "Starting headless display");
+
+    thread::spawn(entrypoint);
+
+    let mut frame: u64 = 0;
+    let mut next_frame = Instant::now();
+
+    // Like the other frontends, this keeps running after the program stops so that it can be
+    // restarted. Set `exit-on-stop` to shut down as soon as the program stops instead.
+    while !lifecycle::exit_requested() {
+        next_frame += *DISPLAY_UPDATE_PERIOD;
+        thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+
+        let mut disp = DISPLAY.lock();
+        disp.render();
+        // There's no output to update, so which parts of the display changed doesn't matter.
+        disp.take_dirty();
+
+        let is_periodic = snapshots.every.is_some_and(|every| frame % every == 0);
+        if is_periodic || SNAPSHOT_REQUESTED.swap(false, Ordering::Relaxed) {
+            snapshots.save(&disp, &format!("frame-{frame:06}.png"));
+        }
+
+        FRAME_FINISHED.notify_all();
+        frame += 1;
+    }
+
+    if snapshots.on_exit {
+        let mut disp = DISPLAY.lock();
+        disp.render();
+        snapshots.save(&disp, "exit.png");
+    }
+
+    Ok(())
+}
+
+/// Settings for when display snapshots are saved.
+#[derive(Debug)]
+struct SnapshotOptions {
+    directory: PathBuf,
+    every: Option<u64>,
+    on_exit: bool,
+}
+
+impl SnapshotOptions {
+    fn from_config() -> anyhow::Result<Self> {
+        let config = config::get();
+        let directory = config.snapshot_dir.clone();
+        let every = config.snapshot_every;
+
+        fs::create_dir_all(&directory).context("Failed to create snapshot directory")?;
+
+        Ok(Self {
+            directory,
+            every: (every != 0).then_some(every),
+            on_exit: config.snapshot_on_exit,
+        })
+    }
+
+    fn save(&self, display: &SimDisplay, file_name: &str) {
+        let path = self.directory.join(file_name);
+
+        match display.save_png(&path) {
+            Ok(()) => info!(path = %path.display(), "Saved display snapshot"),
+            Err(error) => error!(%error, path = %path.display(), "Failed to save display snapshot"),
+        }
+    }
+}
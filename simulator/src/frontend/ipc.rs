@@ -1,58 +1,222 @@
-use std::{mem::MaybeUninit, ptr, sync::Arc, thread, time::Duration};
+use std::{
+    mem::MaybeUninit,
+    ops::ControlFlow,
+    ptr,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
-use roboscope_ipc::{Config, DisplayFrame, SimServices};
-use tracing::trace;
+use roboscope_ipc::{
+    ControlCommand, DisplayFrame, FrameInfo, FrameRect, ProgramState, ProgramStatus, SimServices,
+    Subscriber, TouchInput,
+};
+use tracing::{trace, warn};
 
 use crate::{
+    canvas::Point,
+    config,
     device::start_device_handler,
     display::{DISPLAY, FRAME_FINISHED},
+    journal::JOURNAL,
+    lifecycle,
 };
 
 pub fn start(name: &str, entrypoint: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
     DISPLAY.lock().set_program_name(name);
 
+    let config = config::get();
     let ipc = Arc::new(SimServices::join(
         Some("vex-sdk-desktop"),
-        &Config::default(),
+        &config.robot,
+        &config.ipc.config()?,
     )?);
 
-    start_renderer(ipc.clone());
-    start_device_handler(ipc.clone());
-    let user_code = thread::spawn(entrypoint);
+    let status_publisher = ipc.program_status()?.publisher_builder().create()?;
+    status_publisher.send_copy(ProgramStatus::default())?;
+
+    let threads = [
+        start_renderer(ipc.clone()),
+        start_draw_call_publisher(ipc.clone()),
+        start_device_handler(ipc.clone()),
+    ];
+    let control = ipc.control()?.subscriber_builder().create()?;
+    thread::spawn(entrypoint);
+
+    // Like the other frontends, the simulator keeps running after the program stops so that it
+    // can be restarted, until it's interrupted or asked to exit.
+    let mut last_status = ProgramStatus::default();
+    while !lifecycle::exit_requested() && ipc.node.wait(Duration::from_millis(10)).is_ok() {
+        while let Some(command) = control.receive()? {
+            match *command {
+                ControlCommand::Restart => lifecycle::restart(),
+            }
+        }
+
+        let status = match lifecycle::stopped() {
+            Some(exit_code) => ProgramStatus {
+                state: ProgramState::Stopped,
+                exit_code,
+            },
+            None => ProgramStatus::default(),
+        };
+        if status != last_status {
+            status_publisher.send_copy(status)?;
+            last_status = status;
+        }
+    }
+
+    // Make sure the other threads stop too, even if the wait was interrupted by a signal.
+    lifecycle::request_exit(lifecycle::exit_code());
 
-    while ipc.node.wait(Duration::from_millis(10)).is_ok() {
-        if user_code.is_finished() {
-            break;
+    for thread in threads {
+        if thread.join().is_err() {
+            warn!("Simulator thread panicked while shutting down");
         }
     }
 
+    status_publisher.send_copy(ProgramStatus {
+        state: ProgramState::Stopped,
+        exit_code: lifecycle::exit_code(),
+    })?;
+
     Ok(())
 }
 
-fn start_renderer(ipc: Arc<SimServices>) {
+fn start_renderer(ipc: Arc<SimServices>) -> JoinHandle<()> {
     thread::Builder::new()
         .name("Sim Display Render".into())
         .spawn(move || {
-            // SAFETY: render_frame initializes the frame
+            let mut sequence = 0;
+            let mut touch_input = TouchInputReceiver::new(&ipc).unwrap();
+
+            // SAFETY: publish_frame initializes the frame
             unsafe {
-                ipc.publish_display(publish_frame).unwrap();
+                ipc.publish_display(|frame| {
+                    let stopping = lifecycle::exit_requested();
+                    if let Some(exit_code) = lifecycle::stopped() {
+                        DISPLAY.lock().show_program_ended(exit_code);
+                    }
+
+                    touch_input.apply();
+                    publish_frame(frame, sequence);
+                    sequence += 1;
+
+                    if stopping {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                })
+                .unwrap();
             }
         })
-        .unwrap();
+        .unwrap()
+}
+
+/// Streams new entries in the draw-call journal to subscribers.
+///
+/// The publisher always runs, so entries are streamed whenever the journal is enabled, even if it
+/// was enabled after the simulator started. Nothing is published while it's disabled.
+fn start_draw_call_publisher(ipc: Arc<SimServices>) -> JoinHandle<()> {
+    thread::Builder::new()
+        .name("Sim Draw Call Publisher".into())
+        .spawn(move || {
+            let mut next_index = 0;
+
+            ipc.publish_draw_calls(|batch| {
+                let stopping = lifecycle::exit_requested();
+
+                let journal = JOURNAL.lock();
+                if let Err(error) = journal.write_json_lines_since(next_index, batch) {
+                    warn!(%error, "Failed to encode draw calls");
+                }
+                next_index = journal.next_index();
+
+                if stopping {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        })
+        .unwrap()
+}
+
+/// Receives touchscreen input sent by viewers and applies it to the simulated display.
+struct TouchInputReceiver {
+    subscriber: Subscriber<TouchInput>,
+    /// Input which changes whether the screen is pressed, held back until the next frame so that
+    /// quick taps aren't lost.
+    pending: Option<TouchInput>,
+}
+
+impl TouchInputReceiver {
+    fn new(ipc: &SimServices) -> anyhow::Result<Self> {
+        Ok(Self {
+            subscriber: ipc.touch_input()?.subscriber_builder().create()?,
+            pending: None,
+        })
+    }
+
+    /// Apply the input received since the last frame, stopping at the second press or release.
+    fn apply(&mut self) {
+        let mut disp = DISPLAY.lock();
+        let mut pressed_changed = false;
+
+        loop {
+            let touch = match self.pending.take() {
+                Some(touch) => touch,
+                None => match self.subscriber.receive() {
+                    Ok(Some(touch)) => *touch,
+                    Ok(None) => break,
+                    Err(error) => {
+                        warn!(%error, "Failed to receive touch input");
+                        break;
+                    }
+                },
+            };
+
+            if touch.pressed != disp.mouse_down {
+                if pressed_changed {
+                    self.pending = Some(touch);
+                    break;
+                }
+
+                pressed_changed = true;
+            }
+
+            trace!(?touch, "Received touch input");
+            disp.mouse_coords = Point::new(touch.x, touch.y);
+            disp.mouse_down = touch.pressed;
+        }
+    }
 }
 
 /// Renders a frame by copying the current display data into the given buffer, initializing it.
-fn publish_frame(frame: &mut MaybeUninit<DisplayFrame>) {
+fn publish_frame(frame: &mut MaybeUninit<DisplayFrame>, sequence: u64) {
     let mut disp = DISPLAY.lock();
     disp.render();
 
-    trace!("Publishing a frame");
+    let info = FrameInfo {
+        sequence,
+        timestamp_us: disp.program_time().as_micros() as u64,
+        dirty: disp.take_dirty().map(FrameRect::from).unwrap_or_default(),
+        header_visible: !disp.header_hidden,
+        autorender: disp.autorender,
+    };
+    trace!(?info, "Publishing a frame");
 
+    // The loaned frame could be holding any previously published frame (or nothing at all), so
+    // the whole buffer is copied even if only part of the display changed.
     let frame_ptr = frame.as_mut_ptr();
     unsafe {
         let source = &raw const disp.buffer;
         let destination = &raw mut (*frame_ptr).buffer;
         source.copy_to(destination, 1);
+
+        (&raw mut (*frame_ptr).info).write(info);
     }
 
     FRAME_FINISHED.notify_all();
@@ -2,11 +2,16 @@
 
 use cfg_if::cfg_if;
 
-
 cfg_if! {
  if #[cfg(feature = "windowed")] {
     mod windowed;
     pub use windowed::start;
+ } else if #[cfg(feature = "terminal")] {
+    mod terminal;
+    pub use terminal::start;
+ } else if #[cfg(feature = "headless")] {
+    mod headless;
+    pub use headless::{request_snapshot, start};
  } else {
     mod ipc;
     pub use ipc::start;
@@ -0,0 +1,64 @@
+//! Display renderer implementation which draws the display in a terminal.
+//!
+//! The display is drawn by [`roboscope_ipc::terminal`], which needs a terminal that supports
+//! truecolor escape codes. Clicking and dragging with the left mouse button touches the screen, `r`
+//! restarts the program, and pressing `q`, `Esc` or `Ctrl+C` closes the simulator.
+//!
+//! Log output written to the terminal will garble the display, so it should be redirected to a
+//! file when using this frontend.
+
+use std::{thread, time::Instant};
+
+use roboscope_ipc::{
+    DISPLAY_UPDATE_PERIOD,
+    terminal::{TerminalInput, TerminalScreen},
+};
+
+use crate::{
+    canvas::Point,
+    display::{DISPLAY, FRAME_FINISHED},
+    lifecycle,
+};
+
+pub fn start(name: &str, entrypoint: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
+    DISPLAY.lock().set_program_name(name);
+
+    let mut screen = TerminalScreen::enter()?;
+    thread::spawn(entrypoint);
+
+    let mut next_frame = Instant::now();
+
+    'frames: while !lifecycle::exit_requested() {
+        next_frame += *DISPLAY_UPDATE_PERIOD;
+        thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+
+        for input in screen.poll_input()? {
+            match input {
+                TerminalInput::Quit => {
+                    lifecycle::request_exit(lifecycle::exit_code());
+                    break 'frames;
+                }
+                TerminalInput::Restart => lifecycle::restart(),
+                TerminalInput::Touch { x, y, pressed } => {
+                    let mut disp = DISPLAY.lock();
+                    disp.mouse_coords = Point::new(x, y);
+                    disp.mouse_down = pressed;
+                }
+            }
+        }
+
+        let mut disp = DISPLAY.lock();
+        if let Some(exit_code) = lifecycle::stopped() {
+            disp.show_program_ended(exit_code);
+        }
+        disp.render();
+
+        if disp.take_dirty().is_some() || screen.needs_redraw() {
+            screen.draw(&disp.buffer)?;
+        }
+
+        FRAME_FINISHED.notify_all();
+    }
+
+    Ok(())
+}
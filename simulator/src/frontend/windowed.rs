@@ -3,7 +3,7 @@
 use std::{
     mem,
     num::NonZeroU32,
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::LazyLock,
     thread,
@@ -17,20 +17,24 @@ use fast_image_resize::{
     pixels::U8x4,
 };
 use parking_lot::{Condvar, Mutex};
+use roboscope_ipc::screenshot::timestamped_name;
 use softbuffer::{Context, Surface};
-use tracing::{debug, error, trace};
+use tracing::{debug, error, info, trace};
 use vex_sdk::{V5_TouchEvent, V5_TouchStatus};
 use winit::{
     application::ApplicationHandler,
-    dpi::LogicalSize,
+    dpi::{LogicalSize, PhysicalSize},
     event::{ElementState, MouseButton, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop, OwnedDisplayHandle},
+    keyboard::{Key, ModifiersState, NamedKey},
     window::{Theme, Window, WindowId},
 };
 
 use crate::{
     canvas::{BUFSZ, CANVAS, Canvas, HEIGHT, Point, Rect, WIDTH, img::SimImage},
+    config::{self, DisplayTheme},
     display::{DISPLAY, FRAME_FINISHED},
+    lifecycle,
 };
 
 #[cfg(target_os = "macos")]
@@ -109,6 +113,15 @@ impl<E: FnOnce() + Send + 'static> ApplicationHandler<()> for SimulatorApp<E> {
             StartCause::ResumeTimeReached {
                 requested_resume, ..
             } => {
+                if lifecycle::exit_requested() {
+                    event_loop.exit();
+                    return;
+                }
+
+                if let Some(exit_code) = lifecycle::stopped() {
+                    DISPLAY.lock().show_program_ended(exit_code);
+                }
+
                 // 60Hz render timer has triggered, so render a frame.
                 self.schedule_render(event_loop, requested_resume);
 
@@ -145,11 +158,16 @@ pub struct SimDisplayWindow {
     surface: Surface<OwnedDisplayHandle, Rc<Window>>,
 
     scale_factor: f64,
+    fb_size: PhysicalSize<u32>,
+    modifiers: ModifiersState,
 
-    // A frame has been explicitly requested by the app; the next redraw should autorender the
-    // canvas, update the program header, notify vexDisplayRender callers, etc. instead of just
-    // scaling the previous rendered frame.
+    // A new frame has been rendered; the next redraw should notify vexDisplayRender callers once
+    // it has been presented.
     has_scheduled_frame: bool,
+
+    // The window's framebuffer needs to be redrawn even if the display hasn't changed, e.g.
+    // because the window was resized.
+    needs_redraw: bool,
 }
 
 impl SimDisplayWindow {
@@ -163,7 +181,10 @@ impl SimDisplayWindow {
             .with_resizable(false)
             .with_min_inner_size(WINDOW_SIZE)
             .with_inner_size(WINDOW_SIZE)
-            .with_theme(Some(Theme::Dark))
+            .with_theme(Some(match config::get().theme {
+                DisplayTheme::Dark => Theme::Dark,
+                DisplayTheme::Light => Theme::Light,
+            }))
             .with_title(format!("VEX V5 Simulator (Program: {name})"));
 
         let window = Rc::new(event_loop.create_window(attrs)?);
@@ -182,9 +203,12 @@ impl SimDisplayWindow {
 
         Ok(Self {
             surface,
-            window,
             scale_factor: 1.0,
-            has_scheduled_frame: true,
+            fb_size: window.inner_size(),
+            modifiers: ModifiersState::empty(),
+            has_scheduled_frame: false,
+            needs_redraw: true,
+            window,
         })
     }
 
@@ -222,6 +246,8 @@ impl SimDisplayWindow {
                 }
 
                 self.scale_factor = WINDOW_SIZE.width / fb_dims.width as f64;
+                self.fb_size = fb_dims;
+                self.needs_redraw = true;
 
                 // Scale the framebuffer to the window.
                 self.surface
@@ -231,6 +257,20 @@ impl SimDisplayWindow {
                     )
                     .unwrap();
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && !event.repeat =>
+            {
+                match &event.logical_key {
+                    Key::Character(ch) if ch.eq_ignore_ascii_case("s") => {
+                        self.save_screenshot(self.modifiers.shift_key());
+                    }
+                    Key::Named(NamedKey::F5) => lifecycle::restart(),
+                    _ => {}
+                }
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 let mut display = DISPLAY.lock();
                 display.mouse_coords = Point {
@@ -250,25 +290,51 @@ impl SimDisplayWindow {
         }
     }
 
+    /// Render a new frame, then schedule a redraw of the window if the frame changed.
     pub fn queue_redraw(&mut self) {
-        self.has_scheduled_frame = true;
-        self.window.request_redraw();
+        let mut disp = DISPLAY.lock();
+        disp.render();
+
+        if disp.take_dirty().is_some() || self.needs_redraw {
+            self.has_scheduled_frame = true;
+            self.window.request_redraw();
+        } else {
+            // Nothing changed, so the frame that's already in the window is up to date.
+            FRAME_FINISHED.notify_all();
+        }
     }
 
     pub fn window_id(&self) -> WindowId {
         self.window.id()
     }
 
+    /// Save the display to a timestamped PNG in the current directory, optionally along with a
+    /// copy scaled to the size of the window.
+    ///
+    /// Bound to the S key (or Shift+S to include the scaled copy).
+    pub fn save_screenshot(&self, include_scaled: bool) {
+        let name = timestamped_name("screenshot");
+        let path = PathBuf::from(format!("{name}.png"));
+        let disp = DISPLAY.lock();
+
+        let mut result = disp.save_png(&path);
+        if include_scaled && result.is_ok() {
+            let scaled_path = format!("{name}-scaled.png");
+            result = disp.save_scaled_png(scaled_path, self.fb_size.width, self.fb_size.height);
+        }
+
+        match result {
+            Ok(()) => info!(path = %path.display(), "Saved screenshot"),
+            Err(error) => error!(%error, "Failed to save screenshot"),
+        }
+    }
+
     /// Scale the display's contents to the size of the window, then write them to the framebuffer.
     pub fn redraw(&mut self) {
-        let mut disp = DISPLAY.lock();
+        let disp = DISPLAY.lock();
 
         let is_scheduled = mem::take(&mut self.has_scheduled_frame);
-
-        // Only do updates on 60fps frames to maintain hardware FPS simulation
-        if is_scheduled {
-            disp.render();
-        }
+        self.needs_redraw = false;
 
         let mut framebuffer = self.surface.buffer_mut().unwrap();
         let width = framebuffer.width().get();
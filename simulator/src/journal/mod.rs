@@ -0,0 +1,434 @@
+//! Structured journal of the display SDK calls made by a program.
+//!
+//! When enabled, every call to a display function in [`sdk`](crate::sdk) is recorded as a
+//! [`DrawCall`] which describes the primitive that was drawn along with the canvas state (colors,
+//! font, clip region, etc.) at the time. The most recent calls are kept in a bounded ring which
+//! can be inspected by tests, saved as JSON Lines, or streamed to other processes over IPC.
+//!
+//! The journal is disabled by default. It can be enabled with [`Journal::set_capacity`] or by
+//! setting the `journal-capacity` [config](crate::config) option to the number of calls to keep.
+//!
+//! ```no_run
+//! use vex_sdk_desktop::journal::{DrawOp, JOURNAL};
+//!
+//! JOURNAL.lock().set_capacity(1024);
+//!
+//! // ...run the program...
+//!
+//! let journal = JOURNAL.lock();
+//! let call = journal.find_text("Auton: Left").expect("auton name was drawn");
+//! assert!(matches!(call.op, DrawOp::String { line: Some(3), .. }));
+//! ```
+//!
+//! Recorded calls can be drawn again on a fresh canvas using a [`Replay`].
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+    sync::LazyLock,
+    time::Instant,
+};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    canvas::{Canvas, HEADER_HEIGHT, Rect},
+    config,
+    sdk::current_task_index,
+};
+
+pub use replay::{Replay, ReplayStep};
+
+mod replay;
+
+/// The journal of draw calls made by user code.
+pub static JOURNAL: LazyLock<Mutex<Journal>> = LazyLock::new(|| Mutex::new(Journal::from_config()));
+
+/// A single call to a display SDK function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrawCall {
+    /// The number of draw calls recorded before this one.
+    pub index: u64,
+    /// The time the call was made, measured in microseconds since the journal was started.
+    pub timestamp_us: u64,
+    /// The index of the VEXos task which made the call.
+    pub task: usize,
+    /// The operation that was performed.
+    #[serde(flatten)]
+    pub op: DrawOp,
+    /// The state of the canvas after the call.
+    pub state: DrawState,
+}
+
+impl DrawCall {
+    /// Get the text drawn by this call, if it drew a string.
+    pub fn text(&self) -> Option<&str> {
+        match &self.op {
+            DrawOp::String { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// A display SDK operation.
+///
+/// Coordinates are in the same space as the SDK functions, i.e. relative to the top of the user
+/// canvas below the program header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "primitive", rename_all = "snake_case")]
+pub enum DrawOp {
+    ForegroundColor {
+        color: u32,
+    },
+    BackgroundColor {
+        color: u32,
+    },
+    PixelSet {
+        x: i32,
+        y: i32,
+    },
+    PixelClear {
+        x: i32,
+        y: i32,
+    },
+    LineDraw {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    LineClear {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    RectDraw {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    RectClear {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    RectFill {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    CircleDraw {
+        x: i32,
+        y: i32,
+        radius: i32,
+    },
+    CircleClear {
+        x: i32,
+        y: i32,
+        radius: i32,
+    },
+    CircleFill {
+        x: i32,
+        y: i32,
+        radius: i32,
+    },
+    /// An image copied onto the canvas. The pixels are stored row-by-row without any padding.
+    CopyRect {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        pixels: Vec<u32>,
+    },
+    TextSize {
+        numerator: u32,
+        denominator: u32,
+    },
+    FontNamed {
+        name: String,
+    },
+    PenSize {
+        width: u32,
+    },
+    ClipRegion {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    ClipRegionWithIndex {
+        index: i32,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    /// A string drawn with the current font and colors.
+    String {
+        /// The position of the upper-left corner of the text.
+        x: i32,
+        y: i32,
+        /// The line number the text was drawn on, for functions that take one.
+        line: Option<i32>,
+        text: String,
+        /// Whether the background color was filled behind the text.
+        opaque: bool,
+    },
+    Render {
+        vsync_wait: bool,
+    },
+    DoubleBufferDisable,
+}
+
+/// The state of the canvas when a draw call was made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrawState {
+    pub fg_color: u32,
+    pub bg_color: u32,
+    pub pen_size: u32,
+    /// The ID the current font is registered under, e.g. `NotoMono_49pt`. This may differ from
+    /// the name passed to `vexDisplayFontNamedSet`, but selects the same font when passed to it.
+    pub font: String,
+    pub font_scale: (u32, u32),
+    /// The clip region of the task making the call, as inclusive `[x1, y1, x2, y2]` coordinates.
+    pub clip_region: [i32; 4],
+}
+
+impl DrawState {
+    fn new(canvas: &Canvas) -> Self {
+        let state = &canvas.state;
+        let clip = state.clip_region();
+
+        Self {
+            fg_color: state.fg_color,
+            bg_color: state.bg_color,
+            pen_size: state.pen_size,
+            font: state.font.id().to_string(),
+            font_scale: state.font_scale,
+            clip_region: [
+                clip.left(),
+                clip.top() - HEADER_HEIGHT,
+                clip.1.x - 1,
+                clip.1.y - 1 - HEADER_HEIGHT,
+            ],
+        }
+    }
+
+    /// Get the clip region as a rectangle on the canvas.
+    pub(crate) fn clip_rect(&self) -> Rect {
+        let [x1, y1, x2, y2] = self.clip_region;
+        Rect::from_sdk(x1, y1, x2, y2)
+    }
+}
+
+/// A bounded log of the most recent draw calls.
+pub struct Journal {
+    calls: VecDeque<DrawCall>,
+    capacity: usize,
+    next_index: u64,
+    started: Instant,
+}
+
+impl Journal {
+    /// Create a new journal, reading its capacity from the simulator config.
+    fn from_config() -> Self {
+        Self::new(config::get().journal_capacity)
+    }
+
+    fn new(capacity: usize) -> Self {
+        Self {
+            calls: VecDeque::with_capacity(capacity),
+            capacity,
+            next_index: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Returns `true` if draw calls are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Get the maximum number of draw calls which are kept in the journal.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Set the maximum number of draw calls which are kept in the journal, discarding the oldest
+    /// ones if there are too many. A capacity of zero disables the journal.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.calls.len() > capacity {
+            self.calls.pop_front();
+        }
+    }
+
+    /// Remove all recorded draw calls.
+    pub fn clear(&mut self) {
+        self.calls.clear();
+    }
+
+    /// Iterate over the recorded draw calls, from oldest to newest.
+    pub fn calls(&self) -> impl DoubleEndedIterator<Item = &DrawCall> {
+        self.calls.iter()
+    }
+
+    /// Iterate over the recorded draw calls with an index of at least `index`.
+    pub fn calls_since(&self, index: u64) -> impl DoubleEndedIterator<Item = &DrawCall> {
+        self.calls.iter().filter(move |call| call.index >= index)
+    }
+
+    /// Get the index that will be assigned to the next recorded draw call.
+    pub fn next_index(&self) -> u64 {
+        self.next_index
+    }
+
+    /// Find the most recent call which drew the given text.
+    pub fn find_text(&self, text: &str) -> Option<&DrawCall> {
+        self.calls().rev().find(|call| call.text() == Some(text))
+    }
+
+    /// Write the recorded draw calls to the given writer as JSON Lines.
+    pub fn write_json_lines(&self, writer: impl Write) -> io::Result<()> {
+        self.write_json_lines_since(0, writer)
+    }
+
+    /// Write the recorded draw calls with an index of at least `index` to the given writer as
+    /// JSON Lines.
+    pub fn write_json_lines_since(&self, index: u64, mut writer: impl Write) -> io::Result<()> {
+        for call in self.calls_since(index) {
+            serde_json::to_writer(&mut writer, call)?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()
+    }
+
+    /// Save the recorded draw calls to a JSON Lines file.
+    pub fn save_json_lines(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path.as_ref())?;
+        self.write_json_lines(BufWriter::new(file))?;
+        Ok(())
+    }
+
+    fn push(&mut self, canvas: &Canvas, op: DrawOp) {
+        if self.calls.len() >= self.capacity {
+            self.calls.pop_front();
+        }
+
+        self.calls.push_back(DrawCall {
+            index: self.next_index,
+            timestamp_us: self.started.elapsed().as_micros() as u64,
+            task: current_task_index(),
+            op,
+            state: DrawState::new(canvas),
+        });
+
+        self.next_index += 1;
+    }
+}
+
+/// Record a draw call made using the given canvas, if the journal is enabled.
+///
+/// The operation is only built if it is going to be recorded, so it's fine for it to do expensive
+/// things like copying image data.
+pub(crate) fn record(canvas: &Canvas, op: impl FnOnce() -> DrawOp) {
+    let mut journal = JOURNAL.lock();
+
+    if journal.is_enabled() {
+        journal.push(canvas, op());
+    }
+}
+
+/// Parse draw calls from JSON Lines, as written by [`Journal::write_json_lines`].
+///
+/// Blank lines are skipped.
+pub fn read_json_lines(reader: impl BufRead) -> anyhow::Result<Vec<DrawCall>> {
+    let mut calls = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let call = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid draw call on line {}", line_number + 1))?;
+        calls.push(call);
+    }
+
+    Ok(calls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines_round_trip() {
+        let mut canvas = Canvas::new();
+        let mut journal = Journal::new(16);
+
+        journal.push(&canvas, DrawOp::ForegroundColor { color: 0xFF_80_00 });
+        canvas.state.pen_size = 3;
+        journal.push(&canvas, DrawOp::PenSize { width: 3 });
+        journal.push(
+            &canvas,
+            DrawOp::CircleDraw {
+                x: 100,
+                y: 80,
+                radius: 20,
+            },
+        );
+        journal.push(
+            &canvas,
+            DrawOp::CopyRect {
+                x1: 0,
+                y1: 0,
+                x2: 1,
+                y2: 0,
+                pixels: vec![0x12_34_56, 0xAB_CD_EF],
+            },
+        );
+        journal.push(
+            &canvas,
+            DrawOp::String {
+                x: 10,
+                y: 20,
+                line: Some(2),
+                text: "Auton: \"Left\" ✓\n".to_string(),
+                opaque: true,
+            },
+        );
+        journal.push(&canvas, DrawOp::Render { vsync_wait: false });
+
+        let mut json = Vec::new();
+        journal.write_json_lines(&mut json).unwrap();
+        assert_eq!(json.iter().filter(|&&byte| byte == b'\n').count(), 6);
+
+        let calls = read_json_lines(json.as_slice()).unwrap();
+        assert_eq!(calls, journal.calls().cloned().collect::<Vec<_>>());
+        assert_eq!(calls[2].state.pen_size, 3);
+
+        let mut json = Vec::new();
+        journal.write_json_lines_since(4, &mut json).unwrap();
+        let calls = read_json_lines(json.as_slice()).unwrap();
+        assert_eq!(calls, journal.calls_since(4).cloned().collect::<Vec<_>>());
+        assert_eq!(calls.len(), 2);
+    }
+
+    #[test]
+    fn read_json_lines_reports_line_numbers() {
+        let error = read_json_lines("\n{\"not\": \"a draw call\"}\n".as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{error}");
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Context;
+use roboscope_ipc::FrameRect;
+use tracing::{debug, warn};
+
+use super::{DrawCall, DrawOp, read_json_lines};
+use crate::{
+    canvas::{Canvas, HEADER_HEIGHT, Point, Rect, ThemeColors},
+    config,
+};
+
+/// Draws a sequence of recorded [`DrawCall`]s onto a fresh canvas.
+///
+/// Before each call is applied, the canvas state (colors, font, pen size and clip region) is set
+/// to the state recorded with it, so a replay looks right even when the journal didn't capture
+/// the start of the program. Only the canvas is replayed: the program header isn't drawn, and
+/// [`DrawOp::Render`] calls don't do anything besides marking where frames were presented.
+///
+/// The journal doesn't record what the canvas looked like before the first call, so the canvas
+/// starts out filled with a background color. By default this is the background of the configured
+/// display theme. Use [`Replay::with_background`] when the journal was recorded with a different
+/// theme.
+///
+/// ```no_run
+/// use vex_sdk_desktop::journal::Replay;
+///
+/// let mut replay = Replay::open("calls.jsonl")?;
+/// replay.save_steps("replay")?;
+/// # anyhow::Ok(())
+/// ```
+pub struct Replay {
+    canvas: Canvas,
+    /// The color the canvas is filled with before any calls are applied.
+    background: u32,
+    calls: Vec<DrawCall>,
+    position: usize,
+}
+
+/// The result of applying a single draw call during a [`Replay`].
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayStep<'a> {
+    /// The call that was applied.
+    pub call: &'a DrawCall,
+    /// The region of the canvas which was drawn to by the call, in display coordinates.
+    ///
+    /// This is empty if the call didn't draw anything (e.g. it only changed the canvas state, or
+    /// everything it drew was outside of the clip region).
+    pub changed: FrameRect,
+}
+
+impl Replay {
+    /// Create a replay of the given draw calls, in the order they were made.
+    pub fn new(calls: impl IntoIterator<Item = DrawCall>) -> Self {
+        let background = ThemeColors::from_config(config::get()).background;
+        Self::with_background(calls, background)
+    }
+
+    /// Create a replay of the given draw calls on a canvas which is initially filled with the
+    /// given `0RGB` color.
+    pub fn with_background(calls: impl IntoIterator<Item = DrawCall>, background: u32) -> Self {
+        Self {
+            canvas: blank_canvas(background),
+            background,
+            calls: calls.into_iter().collect(),
+            position: 0,
+        }
+    }
+
+    /// Create a replay from draw calls encoded as JSON Lines.
+    pub fn from_json_lines(reader: impl BufRead) -> anyhow::Result<Self> {
+        Ok(Self::new(read_json_lines(reader)?))
+    }
+
+    /// Create a replay from a JSON Lines file, like one saved by
+    /// [`Journal::save_json_lines`](super::Journal::save_json_lines).
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open draw call journal {}", path.display()))?;
+        Self::from_json_lines(BufReader::new(file))
+    }
+
+    /// Get all of the calls in the replay.
+    pub fn calls(&self) -> &[DrawCall] {
+        &self.calls
+    }
+
+    /// Get the number of calls which have been applied to the canvas so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns `true` if every call has been applied to the canvas.
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.calls.len()
+    }
+
+    /// Get the raw pixel data of the canvas, in `0RGB` format.
+    pub fn pixels(&self) -> &[u32] {
+        self.canvas.buffer()
+    }
+
+    /// Apply the next call to the canvas, returning it along with the region it changed.
+    ///
+    /// Returns `None` once every call has been applied.
+    pub fn step(&mut self) -> Option<ReplayStep<'_>> {
+        let call = self.calls.get(self.position)?;
+        self.position += 1;
+
+        apply(&mut self.canvas, call);
+        let changed = self
+            .canvas
+            .take_dirty()
+            .map(FrameRect::from)
+            .unwrap_or_default();
+
+        Some(ReplayStep { call, changed })
+    }
+
+    /// Apply every remaining call to the canvas.
+    pub fn run_to_end(&mut self) {
+        while self.step().is_some() {}
+    }
+
+    /// Reset the canvas and apply the first `position` calls, so that the next call to
+    /// [`Replay::step`] applies the call at index `position`.
+    pub fn seek(&mut self, position: usize) {
+        let position = position.min(self.calls.len());
+
+        if position < self.position {
+            self.canvas = blank_canvas(self.background);
+            self.position = 0;
+        }
+
+        while self.position < position {
+            self.step();
+        }
+    }
+
+    /// Save the current contents of the canvas as a PNG image.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.canvas.save_png(path)
+    }
+
+    /// Apply every remaining call, saving the canvas to the given directory after each one that
+    /// drew something.
+    ///
+    /// Images are named after the index of the call that was just applied (e.g.
+    /// `call-000042.png`), so a frame which flickers or is drawn over can be traced back to the
+    /// exact calls responsible.
+    pub fn save_steps(&mut self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create replay directory {}", dir.display()))?;
+
+        while let Some(step) = self.step() {
+            if step.changed.is_empty() {
+                continue;
+            }
+
+            let path = dir.join(format!("call-{:06}.png", step.call.index));
+            debug!(path = %path.display(), primitive = ?step.call.op, "Saving replay step");
+            self.save_png(&path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn blank_canvas(background: u32) -> Canvas {
+    Canvas::with_colors(ThemeColors {
+        background,
+        ..ThemeColors::from_config(config::get())
+    })
+}
+
+/// Draw a single recorded call onto the canvas, mirroring the display SDK functions.
+fn apply(canvas: &mut Canvas, call: &DrawCall) {
+    let state = &call.state;
+    canvas.state.fg_color = state.fg_color;
+    canvas.state.bg_color = state.bg_color;
+    canvas.state.pen_size = state.pen_size;
+    canvas.state.set_named_font(&state.font);
+    canvas.state.font_scale = state.font_scale;
+    canvas.state.set_clip_region(state.clip_rect());
+
+    let point = |x: i32, y: i32| Point {
+        x,
+        y: y + HEADER_HEIGHT,
+    };
+
+    match &call.op {
+        DrawOp::PixelSet { x, y } => canvas.set_pixel(point(*x, *y)),
+        DrawOp::PixelClear { x, y } => {
+            canvas.state.swap_colors();
+            canvas.set_pixel(point(*x, *y));
+            canvas.state.swap_colors();
+        }
+        DrawOp::LineDraw { x1, y1, x2, y2 } => {
+            canvas.draw_line(point(*x1, *y1), point(*x2, *y2));
+        }
+        DrawOp::LineClear { x1, y1, x2, y2 } => {
+            canvas.state.swap_colors();
+            canvas.draw_line(point(*x1, *y1), point(*x2, *y2));
+            canvas.state.swap_colors();
+        }
+        DrawOp::RectDraw { x1, y1, x2, y2 } => {
+            canvas.draw_rect(Rect::from_sdk(*x1, *y1, *x2, *y2));
+        }
+        DrawOp::RectClear { x1, y1, x2, y2 } => {
+            canvas.state.swap_colors();
+            canvas.fill_rect(Rect::from_sdk(*x1, *y1, *x2, *y2));
+            canvas.state.swap_colors();
+        }
+        DrawOp::RectFill { x1, y1, x2, y2 } => {
+            canvas.fill_rect(Rect::from_sdk(*x1, *y1, *x2, *y2));
+        }
+        DrawOp::CircleDraw { x, y, radius } => {
+            canvas.draw_circle(point(*x, *y), (*radius).max(0) as u32);
+        }
+        DrawOp::CircleClear { x, y, radius } => {
+            canvas.state.swap_colors();
+            canvas.fill_circle(point(*x, *y), (*radius).max(0) as u32);
+            canvas.state.swap_colors();
+        }
+        DrawOp::CircleFill { x, y, radius } => {
+            canvas.fill_circle(point(*x, *y), (*radius).max(0) as u32);
+        }
+        DrawOp::CopyRect {
+            x1,
+            y1,
+            x2,
+            y2,
+            pixels,
+        } => {
+            let width = (x2 - x1 + 1).max(0) as usize;
+            let height = (y2 - y1 + 1).max(0) as usize;
+
+            if pixels.len() != width * height {
+                warn!(
+                    index = call.index,
+                    expected = width * height,
+                    actual = pixels.len(),
+                    "Skipping copy_rect call with the wrong number of pixels"
+                );
+                return;
+            }
+
+            // SAFETY: the pixels cover the entire rectangle with a stride of its width.
+            unsafe {
+                canvas.copy_rect(Rect::from_sdk(*x1, *y1, *x2, *y2), pixels.as_ptr(), width);
+            }
+        }
+        DrawOp::String {
+            x,
+            y,
+            text,
+            opaque,
+            ..
+        } => canvas.draw_string(point(*x, *y), text, *opaque),
+        // These only change the canvas state, which has already been restored above, or affect
+        // the display rather than the canvas.
+        DrawOp::ForegroundColor { .. }
+        | DrawOp::BackgroundColor { .. }
+        | DrawOp::TextSize { .. }
+        | DrawOp::FontNamed { .. }
+        | DrawOp::PenSize { .. }
+        | DrawOp::ClipRegion { .. }
+        | DrawOp::ClipRegionWithIndex { .. }
+        | DrawOp::Render { .. }
+        | DrawOp::DoubleBufferDisable => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+
+    #[test]
+    fn replay_matches_live_canvas() {
+        // Use the light theme so the test doesn't pass just because the configured theme happens
+        // to match.
+        let background = ThemeColors::LIGHT.background;
+        let mut canvas = Canvas::with_colors(ThemeColors::LIGHT);
+        let mut journal = Journal::new(64);
+
+        canvas.state.fg_color = 0x00_80_FF;
+        journal.push(&canvas, DrawOp::ForegroundColor { color: 0x00_80_FF });
+        canvas.fill_rect(Rect::from_sdk(10, 10, 60, 40));
+        journal.push(
+            &canvas,
+            DrawOp::RectFill {
+                x1: 10,
+                y1: 10,
+                x2: 60,
+                y2: 40,
+            },
+        );
+
+        canvas.state.pen_size = 3;
+        journal.push(&canvas, DrawOp::PenSize { width: 3 });
+        canvas.draw_circle(
+            Point {
+                x: 200,
+                y: 100 + HEADER_HEIGHT,
+            },
+            30,
+        );
+        journal.push(
+            &canvas,
+            DrawOp::CircleDraw {
+                x: 200,
+                y: 100,
+                radius: 30,
+            },
+        );
+
+        canvas.state.set_clip_region(Rect::from_sdk(0, 0, 300, 150));
+        journal.push(
+            &canvas,
+            DrawOp::ClipRegion {
+                x1: 0,
+                y1: 0,
+                x2: 300,
+                y2: 150,
+            },
+        );
+        canvas.draw_line(
+            Point {
+                x: 0,
+                y: HEADER_HEIGHT,
+            },
+            Point {
+                x: 479,
+                y: 200 + HEADER_HEIGHT,
+            },
+        );
+        journal.push(
+            &canvas,
+            DrawOp::LineDraw {
+                x1: 0,
+                y1: 0,
+                x2: 479,
+                y2: 200,
+            },
+        );
+        canvas.draw_string(
+            Point {
+                x: 20,
+                y: 120 + HEADER_HEIGHT,
+            },
+            "Replay",
+            true,
+        );
+        journal.push(
+            &canvas,
+            DrawOp::String {
+                x: 20,
+                y: 120,
+                line: None,
+                text: "Replay".to_string(),
+                opaque: true,
+            },
+        );
+
+        let mut replay = Replay::with_background(journal.calls().cloned(), background);
+        replay.run_to_end();
+        assert!(replay.is_finished());
+        assert!(replay.pixels() == canvas.buffer().as_slice());
+
+        // Seeking backwards redraws from a canvas with the same background.
+        replay.seek(0);
+        assert!(replay.pixels().iter().all(|&pixel| pixel == background));
+    }
+}
@@ -1,33 +1,105 @@
 #![feature(c_variadic)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
-use std::path::Path;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
 #[cfg(not(feature = "windowed"))]
 use std::sync::Arc;
 
+use anyhow::Context;
 #[cfg(not(feature = "windowed"))]
 use roboscope_ipc::SimServices;
+use tracing::debug;
 
+mod battery;
 mod canvas;
-mod config;
+pub mod config;
 mod display;
+mod error_screen;
 pub mod sdk;
 mod device;
 mod frontend;
+mod lifecycle;
 pub mod error;
+pub mod journal;
+pub mod signature;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
+#[cfg(all(feature = "headless", not(any(feature = "windowed", feature = "terminal"))))]
+pub use frontend::request_snapshot;
+pub use error_screen::{PanicReport, report_panic};
+pub use lifecycle::restart as restart_program;
 
-pub fn run_simulator(entrypoint: impl FnOnce() + Send + 'static) -> anyhow::Result<()> {
+/// Run a program in the simulator, returning the exit code the process should exit with.
+///
+/// Settings are loaded from `roboscope.toml`, `ROBOSCOPE_*` environment variables and the
+/// program's command line arguments before the program starts. See [`config`] for the available
+/// settings. The startup options in the program's [code signature](signature) are applied on top
+/// of them.
+///
+/// The entrypoint is called again each time the program is restarted, on a new thread. The exit
+/// code is `0` unless the program panicked or asked to exit with a different code.
+pub fn run_simulator(entrypoint: impl Fn() + Send + Sync + 'static) -> anyhow::Result<i32> {
     let mut args = std::env::args();
     let path = args.next().unwrap_or_else(|| "Simulator".to_string());
 
+    let args: Vec<String> = args.collect();
     let exe_name = Path::new(&path)
         .file_name()
         .and_then(|str| str.to_str())
         .unwrap_or(&path);
 
+    run_simulator_as(exe_name, &args, entrypoint)
+}
+
+/// Run a program in the simulator under the given name, using the given command line arguments
+/// for its settings instead of the process's.
+///
+/// This is useful for hosts which load the program from somewhere else, rather than being the
+/// program themselves. See [`run_simulator`] for details.
+pub fn run_simulator_as(
+    name: &str,
+    args: &[String],
+    entrypoint: impl Fn() + Send + Sync + 'static,
+) -> anyhow::Result<i32> {
+    let mut config = config::Config::load(Some(args)).context("Invalid simulator settings")?;
+    config.apply_signature_options(signature::options());
+    let exit_on_return = config.exit_on_stop;
+    config::init(config);
+
+    error_screen::install_hook();
+
+    lifecycle::set_program(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(&entrypoint));
+
+        // The program was restarted while this run was still going.
+        if !lifecycle::is_current() {
+            return;
+        }
+
+        let exit_code = match result {
+            Ok(()) => 0,
+            Err(_) => {
+                if let Some(report) = error_screen::take_report() {
+                    error_screen::show(&report);
+                }
+
+                lifecycle::PANIC_EXIT_CODE
+            }
+        };
+
+        lifecycle::program_stopped(exit_code);
+
+        if exit_on_return {
+            debug!("Program returned, stopping the simulator");
+            lifecycle::request_exit(exit_code);
+        }
+    });
 
-    frontend::start(exe_name, entrypoint)?;
+    frontend::start(name, lifecycle::run_program)?;
 
-    Ok(())
+    Ok(lifecycle::exit_code())
 }
@@ -0,0 +1,187 @@
+//! Tracking when the program stops and when the simulator should exit.
+//!
+//! The program is considered stopped once its entrypoint returns or panics. Every frontend keeps
+//! running after that, so the program can be restarted, until it's closed. An exit request (from
+//! [`vexSystemExitRequest`](crate::sdk::vexSystemExitRequest), or the program stopping with the
+//! `exit-on-stop` [setting](crate::config) or the
+//! [`V5_SIG_OPTIONS_EXIT`](vex_sdk::V5_SIG_OPTIONS_EXIT) startup option) always makes the frontend
+//! shut down.
+//!
+//! The program can also be [restarted](restart) without restarting the simulator. Threads can't
+//! be killed, so each run of the program is given a generation number, and threads from an
+//! earlier run are parked forever the next time they call into the SDK (see [`park_if_stale`]).
+//! Threads spawned by the program join the run that was current the first time they call into
+//! the SDK, so a thread which hasn't used the SDK yet when the program restarts is treated as part
+//! of the new run.
+
+use std::{
+    cell::Cell,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::{
+    canvas::{CANVAS, Canvas},
+    device::DEVICES,
+    display::DISPLAY,
+    journal::JOURNAL,
+};
+
+/// The exit code used when the program panics, matching the exit code of a Rust program that
+/// panics on the main thread.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+static LIFECYCLE: Mutex<Lifecycle> = Mutex::new(Lifecycle {
+    stopped: None,
+    exit_requested: None,
+});
+
+/// The program's entrypoint, which is run again whenever the program is restarted.
+static PROGRAM: Mutex<Option<Arc<dyn Fn() + Send + Sync>>> = Mutex::new(None);
+
+/// The number of times the program has been restarted.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The generation of the program run this thread belongs to, if it's running the program.
+    ///
+    /// This is set by [`run_program`] on the program's main thread, and by [`park_if_stale`] on
+    /// threads spawned by the program.
+    static THREAD_GENERATION: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+struct Lifecycle {
+    /// The exit code of the program, once it has stopped.
+    stopped: Option<i32>,
+    /// The exit code the simulator was asked to exit with.
+    exit_requested: Option<i32>,
+}
+
+/// Set the entrypoint run by [`run_program`].
+pub fn set_program(program: impl Fn() + Send + Sync + 'static) {
+    *PROGRAM.lock() = Some(Arc::new(program));
+}
+
+/// Run the program's entrypoint on the current thread.
+///
+/// Frontends call this on a new thread to start the program.
+pub fn run_program() {
+    THREAD_GENERATION.set(Some(GENERATION.load(Ordering::Acquire)));
+
+    let program = PROGRAM.lock().clone().expect("program should be set before it's run");
+    program();
+}
+
+/// Returns `false` if the current thread is running an earlier run of the program which has
+/// since been restarted.
+pub fn is_current() -> bool {
+    THREAD_GENERATION
+        .get()
+        .is_none_or(|generation| generation == GENERATION.load(Ordering::Acquire))
+}
+
+/// Returns `true` if the current thread is running the program (from any run), rather than
+/// being one of the simulator's own threads.
+///
+/// Threads spawned by the program only count once they've called into the SDK.
+pub fn is_program_thread() -> bool {
+    THREAD_GENERATION.get().is_some()
+}
+
+/// Park the current thread forever if it belongs to an earlier run of the program.
+///
+/// Only program threads call into the SDK, so a thread without a generation was spawned by the
+/// program and is recorded as part of the current run.
+///
+/// This must not be called while holding any of the simulator's locks.
+pub fn park_if_stale() {
+    if THREAD_GENERATION.get().is_none() {
+        THREAD_GENERATION.set(Some(GENERATION.load(Ordering::Acquire)));
+        return;
+    }
+
+    if is_current() {
+        return;
+    }
+
+    debug!("Parking thread from a previous run of the program");
+    loop {
+        thread::park();
+    }
+}
+
+/// Reset the display, canvas and devices, then run the program again on a new thread.
+///
+/// Threads from the previous run may still draw to the canvas until they next call into the SDK.
+/// This does nothing if the simulator hasn't started yet.
+///
+/// Only the elapsed time shown in the display header starts over. The SDK's system timers
+/// (`vexSystemTimeGet` and friends) aren't implemented yet, so there's no program timer to reset.
+pub fn restart() {
+    if PROGRAM.lock().is_none() {
+        warn!("Can't restart the program before the simulator has started");
+        return;
+    }
+
+    info!("Restarting program");
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    LIFECYCLE.lock().stopped = None;
+
+    {
+        // Lock order: the display must be locked before the canvas.
+        let mut display = DISPLAY.lock();
+        let mut canvas = CANVAS.lock();
+        *canvas = Canvas::new();
+        display.restart();
+    }
+
+    DEVICES.reset();
+    JOURNAL.lock().clear();
+
+    thread::spawn(run_program);
+}
+
+/// Record that the program's entrypoint has finished, with the given exit code.
+///
+/// This is ignored if it's called from an earlier run of the program.
+pub fn program_stopped(exit_code: i32) {
+    if !is_current() {
+        return;
+    }
+
+    debug!(exit_code, "Program stopped");
+    LIFECYCLE.lock().stopped.get_or_insert(exit_code);
+}
+
+/// Get the program's exit code if it has stopped.
+pub fn stopped() -> Option<i32> {
+    LIFECYCLE.lock().stopped
+}
+
+/// Ask the frontend to shut down the simulator with the given exit code.
+///
+/// Only the first request's exit code is used.
+pub fn request_exit(exit_code: i32) {
+    debug!(exit_code, "Exit requested");
+    LIFECYCLE.lock().exit_requested.get_or_insert(exit_code);
+}
+
+/// Returns `true` if the frontend should shut down the simulator.
+pub fn exit_requested() -> bool {
+    LIFECYCLE.lock().exit_requested.is_some()
+}
+
+/// Get the exit code the simulator should return.
+///
+/// This is the code given to [`request_exit`] if the simulator was asked to exit, otherwise the
+/// program's exit code if it stopped, or `0` if the simulator was closed while it was running.
+pub fn exit_code() -> i32 {
+    let lifecycle = LIFECYCLE.lock();
+    lifecycle.exit_requested.or(lifecycle.stopped).unwrap_or(0)
+}
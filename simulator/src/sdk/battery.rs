@@ -2,26 +2,28 @@
 
 use core::ffi::c_double;
 
+use crate::battery::BATTERY;
+
+/// Get the battery voltage, in millivolts.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexBatteryVoltageGet() -> i32 {
-    super::sdk_unimplemented!("vexBatteryVoltageGet");
-    Default::default()
+    BATTERY.lock().snapshot().voltage
 }
 
+/// Get the current drawn from the battery, in milliamps.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexBatteryCurrentGet() -> i32 {
-    super::sdk_unimplemented!("vexBatteryCurrentGet");
-    Default::default()
+    BATTERY.lock().snapshot().current
 }
 
+/// Get the battery temperature, in degrees Celsius.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexBatteryTemperatureGet() -> c_double {
-    super::sdk_unimplemented!("vexBatteryTemperatureGet");
-    Default::default()
+    BATTERY.lock().snapshot().temperature
 }
 
+/// Get the remaining battery capacity, in percent.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexBatteryCapacityGet() -> c_double {
-    super::sdk_unimplemented!("vexBatteryCapacityGet");
-    Default::default()
+    BATTERY.lock().snapshot().capacity
 }
@@ -8,25 +8,31 @@ use std::{
     mem::MaybeUninit,
     ptr,
 };
-use tracing::trace;
+use tracing::{trace, warn};
 
 pub use vex_sdk::v5_image;
 
 use crate::{
     canvas::{CANVAS, Canvas, HEADER_HEIGHT, Point, Rect, WIDTH},
     display::{DISPLAY, SimDisplay},
+    journal::{self, DrawOp},
+    lifecycle,
 };
 
 /// Set the foreground color.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayForegroundColor(col: u32) {
-    CANVAS.lock().state.fg_color = col;
+    let mut canvas = CANVAS.lock();
+    canvas.state.fg_color = col;
+    journal::record(&canvas, || DrawOp::ForegroundColor { color: col });
 }
 
 /// Set the background color.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayBackgroundColor(col: u32) {
-    CANVAS.lock().state.bg_color = col;
+    let mut canvas = CANVAS.lock();
+    canvas.state.bg_color = col;
+    journal::record(&canvas, || DrawOp::BackgroundColor { color: col });
 }
 
 #[unsafe(no_mangle)]
@@ -67,13 +73,32 @@ pub unsafe extern "system" fn vexDisplayCopyRect(
     pSrc: *mut u32,
     srcStride: i32,
 ) {
+    let stride = srcStride.max(0) as usize;
+
+    let mut canvas = CANVAS.lock();
     unsafe {
-        CANVAS.lock().copy_rect(
-            Rect::from_sdk(x1, y1, x2, y2),
-            pSrc,
-            srcStride.max(0) as usize,
-        );
+        canvas.copy_rect(Rect::from_sdk(x1, y1, x2, y2), pSrc, stride);
     }
+
+    journal::record(&canvas, || {
+        let width = (x2 - x1 + 1).max(0) as usize;
+        let height = (y2 - y1 + 1).max(0) as usize;
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            // SAFETY: the caller guarantees every row of the source image is valid for reads.
+            let row = unsafe { std::slice::from_raw_parts(pSrc.add(row * stride), width) };
+            pixels.extend_from_slice(row);
+        }
+
+        DrawOp::CopyRect {
+            x1,
+            y1,
+            x2,
+            y2,
+            pixels,
+        }
+    });
 }
 
 /// Write a pixel using the foreground color.
@@ -84,6 +109,10 @@ pub extern "system" fn vexDisplayPixelSet(x: u32, y: u32) {
         x: x as i32,
         y: y as i32 + HEADER_HEIGHT,
     });
+    journal::record(&canvas, || DrawOp::PixelSet {
+        x: x as i32,
+        y: y as i32,
+    });
 }
 
 /// Write a pixel using the background color.
@@ -96,9 +125,13 @@ pub extern "system" fn vexDisplayPixelClear(x: u32, y: u32) {
         y: y as i32 + HEADER_HEIGHT,
     });
     canvas.state.swap_colors();
+    journal::record(&canvas, || DrawOp::PixelClear {
+        x: x as i32,
+        y: y as i32,
+    });
 }
 
-/// Draw a 1px-wide line using the foreground color.
+/// Draw a line using the foreground color and current pen size.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayLineDraw(x1: i32, y1: i32, x2: i32, y2: i32) {
     let mut canvas = CANVAS.lock();
@@ -112,9 +145,10 @@ pub extern "system" fn vexDisplayLineDraw(x1: i32, y1: i32, x2: i32, y2: i32) {
             y: y2 + HEADER_HEIGHT,
         },
     );
+    journal::record(&canvas, || DrawOp::LineDraw { x1, y1, x2, y2 });
 }
 
-/// Draw a 1px-wide line using the background color.
+/// Draw a line using the background color and current pen size.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayLineClear(x1: i32, y1: i32, x2: i32, y2: i32) {
     let mut canvas = CANVAS.lock();
@@ -130,13 +164,15 @@ pub extern "system" fn vexDisplayLineClear(x1: i32, y1: i32, x2: i32, y2: i32) {
         },
     );
     canvas.state.swap_colors();
+    journal::record(&canvas, || DrawOp::LineClear { x1, y1, x2, y2 });
 }
 
-/// Trace the 1px-wide outline of the given rectangle using the foreground color.
+/// Trace the outline of the given rectangle using the foreground color and current pen size.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayRectDraw(x1: i32, y1: i32, x2: i32, y2: i32) {
     let mut canvas = CANVAS.lock();
     canvas.draw_rect(Rect::from_sdk(x1, y1, x2, y2));
+    journal::record(&canvas, || DrawOp::RectDraw { x1, y1, x2, y2 });
 }
 
 /// Fill the given rectangle of pixels with the background color.
@@ -146,6 +182,7 @@ pub extern "system" fn vexDisplayRectClear(x1: i32, y1: i32, x2: i32, y2: i32) {
     canvas.state.swap_colors();
     canvas.fill_rect(Rect::from_sdk(x1, y1, x2, y2));
     canvas.state.swap_colors();
+    journal::record(&canvas, || DrawOp::RectClear { x1, y1, x2, y2 });
 }
 
 /// Fill the given rectangle of pixels with the foreground color.
@@ -153,9 +190,10 @@ pub extern "system" fn vexDisplayRectClear(x1: i32, y1: i32, x2: i32, y2: i32) {
 pub extern "system" fn vexDisplayRectFill(x1: i32, y1: i32, x2: i32, y2: i32) {
     let mut canvas = CANVAS.lock();
     canvas.fill_rect(Rect::from_sdk(x1, y1, x2, y2));
+    journal::record(&canvas, || DrawOp::RectFill { x1, y1, x2, y2 });
 }
 
-/// Trace the 1px-wide outline of the given circle using the foreground color.
+/// Trace the outline of the given circle using the foreground color and current pen size.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayCircleDraw(xc: i32, yc: i32, radius: i32) {
     let mut canvas = CANVAS.lock();
@@ -165,6 +203,11 @@ pub extern "system" fn vexDisplayCircleDraw(xc: i32, yc: i32, radius: i32) {
         y: yc + HEADER_HEIGHT,
     };
     canvas.draw_circle(point, radius.max(0) as u32);
+    journal::record(&canvas, || DrawOp::CircleDraw {
+        x: xc,
+        y: yc,
+        radius,
+    });
 }
 
 /// Fill the given circle of pixels with the background color.
@@ -180,6 +223,11 @@ pub extern "system" fn vexDisplayCircleClear(xc: i32, yc: i32, radius: i32) {
     canvas.fill_circle(point, radius.max(0) as u32);
 
     canvas.state.swap_colors();
+    journal::record(&canvas, || DrawOp::CircleClear {
+        x: xc,
+        y: yc,
+        radius,
+    });
 }
 
 /// Fill the given circle of pixels with the foreground color.
@@ -192,12 +240,22 @@ pub extern "system" fn vexDisplayCircleFill(xc: i32, yc: i32, radius: i32) {
         y: yc + HEADER_HEIGHT,
     };
     canvas.fill_circle(point, radius.max(0) as u32);
+    journal::record(&canvas, || DrawOp::CircleFill {
+        x: xc,
+        y: yc,
+        radius,
+    });
 }
 
 /// Set the text scaling factor to the given fraction `n / d`.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayTextSize(n: u32, d: u32) {
-    CANVAS.lock().state.font_scale = (n, d);
+    let mut canvas = CANVAS.lock();
+    canvas.state.font_scale = (n, d);
+    journal::record(&canvas, || DrawOp::TextSize {
+        numerator: n,
+        denominator: d,
+    });
 }
 
 /// Set the current font by name.
@@ -221,7 +279,11 @@ pub extern "system" fn vexDisplayTextSize(n: u32, d: u32) {
 pub unsafe extern "system" fn vexDisplayFontNamedSet(pFontName: *const c_char) {
     let c_str = unsafe { CStr::from_ptr(pFontName) };
     let str = c_str.to_str().unwrap_or_default();
-    CANVAS.lock().state.set_named_font(str);
+    let mut canvas = CANVAS.lock();
+    canvas.state.set_named_font(str);
+    journal::record(&canvas, || DrawOp::FontNamed {
+        name: str.to_string(),
+    });
 }
 
 #[unsafe(no_mangle)]
@@ -258,10 +320,17 @@ pub unsafe extern "system" fn vexDisplayStringHeightGet(pString: *const c_char)
     CANVAS.lock().measure_string_height(&str)
 }
 
-/// This function saves the given value and returns it from [`vexDisplayPenSizeGet`].
+/// Sets the width in pixels of lines and outlines drawn on the canvas.
+///
+/// The pen size affects [`vexDisplayLineDraw`], [`vexDisplayRectDraw`], [`vexDisplayCircleDraw`]
+/// and their background color variants. Thick rectangle and circle outlines grow inward from the
+/// edge of the shape, while thick lines are centered on the line. A pen size of zero is treated
+/// the same as a pen size of one.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayPenSizeSet(width: u32) {
-    CANVAS.lock().state.pen_size = width;
+    let mut canvas = CANVAS.lock();
+    canvas.state.pen_size = width;
+    journal::record(&canvas, || DrawOp::PenSize { width });
 }
 
 /// Returns the last value passed to [`vexDisplayPenSizeSet`].
@@ -270,7 +339,7 @@ pub extern "system" fn vexDisplayPenSizeGet() -> u32 {
     CANVAS.lock().state.pen_size
 }
 
-/// Sets the rectangle inside which changes to the canvas are allowed.
+/// Sets the rectangle inside which changes to the canvas are allowed for the current task.
 ///
 /// The default clip region is the entire canvas. Note that unless "fullscreen" mode is enabled in
 /// the simulator config, changes made underneath the header at the top of the screen will
@@ -278,7 +347,9 @@ pub extern "system" fn vexDisplayPenSizeGet() -> u32 {
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayClipRegionSet(x1: i32, y1: i32, x2: i32, y2: i32) {
     let region = Rect::from_sdk(x1, y1, x2, y2);
-    CANVAS.lock().state.set_clip_region(region);
+    let mut canvas = CANVAS.lock();
+    canvas.state.set_clip_region(region);
+    journal::record(&canvas, || DrawOp::ClipRegion { x1, y1, x2, y2 });
 }
 
 /// Renders the contents of the user canvas on the simulated display.
@@ -294,11 +365,15 @@ pub extern "system" fn vexDisplayClipRegionSet(x1: i32, y1: i32, x2: i32, y2: i3
 /// The `bRunScheduler` parameter is currently a no-op.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayRender(bVsyncWait: bool, bRunScheduler: bool) {
+    lifecycle::park_if_stale();
     trace!("Dispatching render");
+    journal::record(&CANVAS.lock(), || DrawOp::Render {
+        vsync_wait: bVsyncWait,
+    });
 
     let do_render = |display: &mut SimDisplay| {
         display.autorender = false;
-        display.render_user_canvas(&CANVAS.lock());
+        display.render_user_canvas(&mut CANVAS.lock());
         // We do not send an event to the renderer telling it to render because that could
         // potentially cause render speeds of more than 60fps which is not true to the V5 hardware.
     };
@@ -313,15 +388,44 @@ pub extern "system" fn vexDisplayRender(bVsyncWait: bool, bRunScheduler: bool) {
 /// Re-enables automatic 60 FPS rendering after a call to [`vexDisplayRender`].
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDisplayDoubleBufferDisable() {
+    journal::record(&CANVAS.lock(), || DrawOp::DoubleBufferDisable);
+
     let mut display = DISPLAY.lock();
     display.autorender = true;
 }
 
-/// Unimplemented.
+/// Sets the rectangle inside which changes to the canvas are allowed for the task with the given
+/// index.
+///
+/// Each task has its own clip region, which is used whenever code running in that task draws to
+/// the canvas. The default task has index 0 and is the one affected by
+/// [`vexDisplayClipRegionSet`]. Threads can be associated with other tasks using
+/// [`set_current_task_index`](super::set_current_task_index).
+///
+/// Indices outside of the range of supported tasks are ignored with a warning.
 #[unsafe(no_mangle)]
-pub extern "system" fn vexDisplayClipRegionSetWithIndex(index: i32, x1: i32, y1: i32, x2: i32, y2: i32) {
-    super::sdk_unimplemented!("vexDisplayClipRegionSetWithIndex");
-    unimplemented!("VEXos task api")
+pub extern "system" fn vexDisplayClipRegionSetWithIndex(
+    index: i32,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+) {
+    let Ok(task) = usize::try_from(index) else {
+        warn!(index, "Ignoring clip region for negative task index");
+        return;
+    };
+
+    let region = Rect::from_sdk(x1, y1, x2, y2);
+    let mut canvas = CANVAS.lock();
+    canvas.state.set_task_clip_region(task, region);
+    journal::record(&canvas, || DrawOp::ClipRegionWithIndex {
+        index,
+        x1,
+        y1,
+        x2,
+        y2,
+    });
 }
 
 /// Unimplemented.
@@ -425,6 +529,68 @@ fn line_to_y(line: i32) -> i32 {
     line * 20 + 2
 }
 
+/// Where a printf-style function draws its text.
+#[derive(Debug, Clone, Copy)]
+enum TextPosition {
+    /// The upper-left corner of the text is at the given coordinates.
+    At { x: i32, y: i32 },
+    /// The text is drawn at the start of the given line.
+    Line(i32),
+    /// The text is drawn in the horizontal center of the given line.
+    CenteredLine(i32),
+}
+
+/// Draws a string on the canvas and records it in the journal.
+fn draw_text(canvas: &mut Canvas, position: TextPosition, text: &str, opaque: bool) {
+    let (x, y, line) = match position {
+        TextPosition::At { x, y } => (x, y, None),
+        TextPosition::Line(line) => (0, line_to_y(line), Some(line)),
+        TextPosition::CenteredLine(line) => {
+            let str_width = canvas.measure_string_width(text);
+            ((WIDTH as i32 - str_width) / 2, line_to_y(line), Some(line))
+        }
+    };
+
+    canvas.draw_string(
+        Point {
+            x,
+            y: y + HEADER_HEIGHT,
+        },
+        text,
+        opaque,
+    );
+    journal::record(canvas, || DrawOp::String {
+        x,
+        y,
+        line,
+        text: text.to_string(),
+        opaque,
+    });
+}
+
+/// Formats a string and draws it with the given font and scale, like the fixed-size string
+/// functions do.
+///
+/// # Safety
+///
+/// See [`vexDisplayPrintf`].
+unsafe fn draw_formatted_text(
+    position: TextPosition,
+    font: &str,
+    font_scale: (u32, u32),
+    format: *const c_char,
+    args: VaList<'_>,
+) {
+    let mut buffer = MaybeUninit::uninit();
+    let string = unsafe { format_string(&mut buffer, format, args) };
+
+    let mut canvas = CANVAS.lock();
+    canvas.state.set_named_font(font);
+    canvas.state.font_scale = font_scale;
+
+    draw_text(&mut canvas, position, &string, true);
+}
+
 /// Variant of [`vexDisplayPrintf`] which accepts a [`VaList`].
 ///
 /// # Safety
@@ -442,12 +608,10 @@ pub unsafe extern "system" fn vexDisplayVPrintf(
     let string = unsafe { format_string(&mut buffer, format, args) };
 
     let mut canvas = CANVAS.lock();
-    canvas.draw_string(
-        Point {
-            x: xpos,
-            y: ypos + HEADER_HEIGHT,
-        },
-        string.as_ref(),
+    draw_text(
+        &mut canvas,
+        TextPosition::At { x: xpos, y: ypos },
+        &string,
         bOpaque != 0,
     );
 }
@@ -464,7 +628,13 @@ pub unsafe extern "system" fn vexDisplayVString(
     args: VaList<'_>,
 ) {
     unsafe {
-        vexDisplayVStringAt(0, line_to_y(nLineNumber), format, args);
+        draw_formatted_text(
+            TextPosition::Line(nLineNumber),
+            "NotoMono_49pt",
+            (1, 3),
+            format,
+            args,
+        );
     }
 }
 
@@ -480,21 +650,15 @@ pub unsafe extern "system" fn vexDisplayVStringAt(
     format: *const c_char,
     args: VaList<'_>,
 ) {
-    let mut buffer = MaybeUninit::uninit();
-    let string = unsafe { format_string(&mut buffer, format, args) };
-
-    let mut canvas = CANVAS.lock();
-    canvas.state.set_named_font("NotoMono_49pt");
-    canvas.state.font_scale = (1, 3);
-
-    canvas.draw_string(
-        Point {
-            x: xpos,
-            y: ypos + HEADER_HEIGHT,
-        },
-        string.as_ref(),
-        true,
-    );
+    unsafe {
+        draw_formatted_text(
+            TextPosition::At { x: xpos, y: ypos },
+            "NotoMono_49pt",
+            (1, 3),
+            format,
+            args,
+        );
+    }
 }
 
 /// Variant of [`vexDisplayVBigString`] which accepts a [`VaList`].
@@ -509,7 +673,13 @@ pub unsafe extern "system" fn vexDisplayVBigString(
     args: VaList<'_>,
 ) {
     unsafe {
-        vexDisplayVBigStringAt(0, line_to_y(nLineNumber), format, args);
+        draw_formatted_text(
+            TextPosition::Line(nLineNumber),
+            "NotoMono_49pt",
+            (2, 3),
+            format,
+            args,
+        );
     }
 }
 
@@ -525,21 +695,15 @@ pub unsafe extern "system" fn vexDisplayVBigStringAt(
     format: *const c_char,
     args: VaList<'_>,
 ) {
-    let mut buffer = MaybeUninit::uninit();
-    let string = unsafe { format_string(&mut buffer, format, args) };
-
-    let mut canvas = CANVAS.lock();
-    canvas.state.set_named_font("NotoMono_49pt");
-    canvas.state.font_scale = (2, 3);
-
-    canvas.draw_string(
-        Point {
-            x: xpos,
-            y: ypos + HEADER_HEIGHT,
-        },
-        string.as_ref(),
-        true,
-    );
+    unsafe {
+        draw_formatted_text(
+            TextPosition::At { x: xpos, y: ypos },
+            "NotoMono_49pt",
+            (2, 3),
+            format,
+            args,
+        );
+    }
 }
 
 /// Variant of [`vexDisplaySmallStringAt`] which accepts a [`VaList`].
@@ -554,21 +718,15 @@ pub unsafe extern "system" fn vexDisplayVSmallStringAt(
     format: *const c_char,
     args: VaList<'_>,
 ) {
-    let mut buffer = MaybeUninit::uninit();
-    let string = unsafe { format_string(&mut buffer, format, args) };
-
-    let mut canvas = CANVAS.lock();
-    canvas.state.set_named_font("NotoMono_39pt");
-    canvas.state.font_scale = (1, 3);
-
-    canvas.draw_string(
-        Point {
-            x: xpos,
-            y: ypos + HEADER_HEIGHT,
-        },
-        string.as_ref(),
-        true,
-    );
+    unsafe {
+        draw_formatted_text(
+            TextPosition::At { x: xpos, y: ypos },
+            "NotoMono_39pt",
+            (1, 3),
+            format,
+            args,
+        );
+    }
 }
 
 /// Variant of [`vexDisplayCenteredString`] which accepts a [`VaList`].
@@ -582,23 +740,15 @@ pub unsafe extern "system" fn vexDisplayVCenteredString(
     format: *const c_char,
     args: VaList<'_>,
 ) {
-    let mut buffer = MaybeUninit::uninit();
-    let string = unsafe { format_string(&mut buffer, format, args) };
-
-    let mut canvas = CANVAS.lock();
-    canvas.state.set_named_font("NotoMono_49pt");
-    canvas.state.font_scale = (1, 3);
-
-    let str_width = canvas.measure_string_width(&string);
-
-    canvas.draw_string(
-        Point {
-            x: (WIDTH as i32 - str_width) / 2,
-            y: line_to_y(nLineNumber) + HEADER_HEIGHT,
-        },
-        string.as_ref(),
-        true,
-    );
+    unsafe {
+        draw_formatted_text(
+            TextPosition::CenteredLine(nLineNumber),
+            "NotoMono_49pt",
+            (1, 3),
+            format,
+            args,
+        );
+    }
 }
 
 /// Variant of [`vexDisplayBigCenteredString`] which accepts a [`VaList`].
@@ -612,23 +762,15 @@ pub unsafe extern "system" fn vexDisplayVBigCenteredString(
     format: *const c_char,
     args: VaList<'_>,
 ) {
-    let mut buffer = MaybeUninit::uninit();
-    let string = unsafe { format_string(&mut buffer, format, args) };
-
-    let mut canvas = CANVAS.lock();
-    canvas.state.set_named_font("NotoMono_49pt");
-    canvas.state.font_scale = (2, 3);
-
-    let str_width = canvas.measure_string_width(&string);
-
-    canvas.draw_string(
-        Point {
-            x: (WIDTH as i32 - str_width) / 2,
-            y: line_to_y(nLineNumber) + HEADER_HEIGHT,
-        },
-        string.as_ref(),
-        true,
-    );
+    unsafe {
+        draw_formatted_text(
+            TextPosition::CenteredLine(nLineNumber),
+            "NotoMono_49pt",
+            (2, 3),
+            format,
+            args,
+        );
+    }
 }
 
 /// Performs printf-style formatting and writes the result to the canvas at the given coordinates.
@@ -643,8 +785,8 @@ pub unsafe extern "system" fn vexDisplayVBigCenteredString(
 /// # Limitations
 ///
 /// No more than 256 bytes of data can be written to the screen at once (any more will be
-/// truncated). Only ASCII-formatted string data with characters between codepoints 32 and 126 is
-/// supported. Unsupported characters will be replaced with a placeholder character.
+/// truncated). The string is interpreted as UTF-8, and characters which the current font doesn't
+/// have a glyph for will be replaced with a placeholder character.
 ///
 /// # Safety
 ///
@@ -2,11 +2,14 @@
 
 use core::ffi::c_double;
 
+use roboscope_ipc::MotorSnapshot;
 use vex_sdk::V5_DeviceT;
 pub use vex_sdk::{
     V5_DeviceMotorPid, V5MotorBrakeMode, V5MotorControlMode, V5MotorEncoderUnits, V5MotorGearset,
 };
 
+use crate::device::DEVICES;
+
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDeviceMotorVelocitySet(device: V5_DeviceT, velocity: i32) {
     super::sdk_unimplemented!("vexDeviceMotorVelocitySet");
@@ -53,10 +56,20 @@ pub extern "system" fn vexDeviceMotorCurrentLimitGet(device: V5_DeviceT) -> i32
     super::sdk_unimplemented!("vexDeviceMotorCurrentLimitGet");
     Default::default()
 }
+/// Get the current drawn by the motor, in milliamps.
+///
+/// # Safety
+///
+/// The device handle must be valid.
 #[unsafe(no_mangle)]
-pub extern "system" fn vexDeviceMotorCurrentGet(device: V5_DeviceT) -> i32 {
-    super::sdk_unimplemented!("vexDeviceMotorCurrentGet");
-    Default::default()
+pub unsafe extern "system" fn vexDeviceMotorCurrentGet(device: V5_DeviceT) -> i32 {
+    let device = unsafe { DEVICES.get_by_handle_unchecked(device) }
+        .as_ref()
+        .lock();
+
+    device
+        .readings::<MotorSnapshot>()
+        .map_or(0, |snapshot| snapshot.current)
 }
 #[unsafe(no_mangle)]
 pub extern "system" fn vexDeviceMotorPowerGet(device: V5_DeviceT) -> c_double {
@@ -55,12 +55,16 @@ pub extern "system" fn vexSystemDigitalIO(pin: u32, value: u32) {
 }
 #[unsafe(no_mangle)]
 pub extern "system" fn vexSystemStartupOptions() -> u32 {
-    super::sdk_unimplemented!("vexSystemStartupOptions");
-    Default::default()
+    crate::signature::options()
 }
+
+/// Stop the program and shut down the simulator.
+///
+/// Unlike VEXos, this doesn't stop the program's threads straight away, but the simulator exits
+/// shortly afterwards.
 #[unsafe(no_mangle)]
 pub extern "system" fn vexSystemExitRequest() {
-    super::sdk_unimplemented!("vexSystemExitRequest");
+    crate::lifecycle::request_exit(0);
 }
 #[unsafe(no_mangle)]
 pub extern "system" fn vexSystemHighResTimeGet() -> u64 {
@@ -1,12 +1,49 @@
 //! VEXos Task Scheduler Functions
 
 use core::ffi::{c_char, c_int, c_void};
-use std::time::{Duration, Instant};
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
 
 use parking_lot::Mutex;
+use tracing::warn;
 use vex_sdk::V5_TouchEvent;
 
-use crate::{canvas::HEADER_HEIGHT, device::DEVICES, display::DISPLAY};
+use crate::{canvas::HEADER_HEIGHT, device::DEVICES, display::DISPLAY, lifecycle};
+
+/// The number of VEXos tasks which can have their own display state, such as a clip region.
+pub const MAX_TASKS: usize = 16;
+
+thread_local! {
+    /// The index of the VEXos task that code on this thread belongs to.
+    static CURRENT_TASK_INDEX: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Get the index of the VEXos task running on the current thread.
+///
+/// Threads belong to the default task (index 0) unless they have been assigned a different one
+/// with [`set_current_task_index`].
+pub fn current_task_index() -> usize {
+    CURRENT_TASK_INDEX.get()
+}
+
+/// Assign the current thread to the VEXos task with the given index.
+///
+/// This is a simulator extension: programs which model VEXos tasks using threads can call this at
+/// the start of each thread so that per-task state like the clip region set by
+/// [`vexDisplayClipRegionSetWithIndex`](super::vexDisplayClipRegionSetWithIndex) applies to it.
+/// [`vexTaskAdd`] isn't implemented, so this is the only way for code to run in a task other than
+/// the default one.
+///
+/// Threads assigned to an index of [`MAX_TASKS`] or above draw with the full clip region.
+pub fn set_current_task_index(index: usize) {
+    if index >= MAX_TASKS {
+        warn!(index, "Task index is out of range, clip regions won't apply to this thread");
+    }
+
+    CURRENT_TASK_INDEX.set(index);
+}
 
 #[unsafe(no_mangle)]
 pub extern "system" fn vexTaskAdd(
@@ -101,6 +138,8 @@ pub fn update_device_readings() {
 
 #[unsafe(no_mangle)]
 pub extern "system" fn vexTasksRun() {
+    lifecycle::park_if_stale();
+
     let mut tasks = TASKS.lock();
     let now = Instant::now();
 
@@ -0,0 +1,120 @@
+//! The program's code signature.
+//!
+//! VEXos reads a [`vcodesig`] from the start of every program to decide how to run it. vexide
+//! embeds this signature in the `.code_signature` section of the executable, so the simulator can
+//! read it from the running program and apply the same startup options:
+//!
+//! - [`V5_SIG_OPTIONS_INDG`](vex_sdk::V5_SIG_OPTIONS_INDG): swap the default foreground and
+//!   background colors of the display.
+//! - [`V5_SIG_OPTIONS_THDG`](vex_sdk::V5_SIG_OPTIONS_THDG): use the light display theme.
+//! - [`V5_SIG_OPTIONS_EXIT`](vex_sdk::V5_SIG_OPTIONS_EXIT): shut down the simulator when the
+//!   program stops, like the `exit-on-stop` [setting](crate::config).
+//!
+//! Programs which aren't built with vexide (or executable formats where the section can't be
+//! found) can provide their signature with [`set`] before calling
+//! [`run_simulator`](crate::run_simulator). The signature doesn't include the program's name, so
+//! the name shown in the display header is still taken from the executable.
+
+use std::{env, fs::File, path::Path, sync::OnceLock};
+
+use anyhow::Context;
+use object::{Object, ObjectSection, read::ReadCache};
+use tracing::{debug, warn};
+use vex_sdk::{V5_SIG_MAGIC, vcodesig};
+
+/// The name of the linker section vexide places the code signature in.
+pub const SECTION_NAME: &str = ".code_signature";
+
+static CODE_SIGNATURE: OnceLock<Option<vcodesig>> = OnceLock::new();
+
+/// Use the given code signature instead of reading it from the executable.
+///
+/// This has no effect if the signature has already been read, so it should be called before
+/// [`run_simulator`](crate::run_simulator).
+pub fn set(signature: vcodesig) {
+    if CODE_SIGNATURE.set(Some(signature)).is_err() {
+        warn!("The code signature was read before it was set, so it will be ignored");
+    }
+}
+
+/// Get the program's code signature, if it has one.
+///
+/// The signature is read from the running executable the first time this is called.
+pub fn get() -> Option<vcodesig> {
+    *CODE_SIGNATURE.get_or_init(|| {
+        let signature = read_from_executable().unwrap_or_else(|error| {
+            warn!(%error, "Failed to read the program's code signature");
+            None
+        });
+
+        match signature {
+            Some(signature) => debug!(?signature, "Loaded code signature"),
+            None => debug!("Program doesn't have a code signature, using the default options"),
+        }
+
+        signature
+    })
+}
+
+/// Get the startup options from the program's code signature, or
+/// [`V5_SIG_OPTIONS_NONE`](vex_sdk::V5_SIG_OPTIONS_NONE) if it doesn't have one.
+pub fn options() -> u32 {
+    get().map_or(vex_sdk::V5_SIG_OPTIONS_NONE, |signature| signature.options)
+}
+
+/// Find the code signature section in the running executable and parse it.
+///
+/// Returns `None` if the executable doesn't have a code signature section.
+fn read_from_executable() -> anyhow::Result<Option<vcodesig>> {
+    let path = env::current_exe().context("Failed to find the program's executable")?;
+    read_from_file(&path)
+}
+
+/// Find the code signature section in an executable or shared library and parse it.
+///
+/// Returns `None` if the file doesn't have a code signature section.
+pub fn read_from_file(path: &Path) -> anyhow::Result<Option<vcodesig>> {
+    // Only the headers and the signature section are read, rather than the whole executable.
+    let data = File::open(path)
+        .map(ReadCache::new)
+        .with_context(|| format!("Failed to open executable {}", path.display()))?;
+    let file = object::File::parse(&data).context("Failed to parse executable")?;
+
+    let Some(section) = file.section_by_name(SECTION_NAME) else {
+        return Ok(None);
+    };
+    let bytes = section
+        .data()
+        .context("Failed to read the code signature section")?;
+
+    let Some(bytes) = bytes.get(..size_of::<vcodesig>()) else {
+        warn!(len = bytes.len(), "Code signature section is too small");
+        return Ok(None);
+    };
+
+    let word = |index: usize| {
+        let bytes = bytes[index * 4..(index + 1) * 4].try_into().unwrap();
+        if file.is_little_endian() {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+
+    let signature = vcodesig {
+        magic: word(0),
+        r#type: word(1),
+        owner: word(2),
+        options: word(3),
+    };
+
+    if { signature.magic } != V5_SIG_MAGIC {
+        warn!(
+            magic = format_args!("{:#010x}", { signature.magic }),
+            "Code signature has the wrong magic number"
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(signature))
+}
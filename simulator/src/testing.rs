@@ -0,0 +1,362 @@
+//! Golden-image testing for display code.
+//!
+//! [`DisplayTest`] runs a program without any frontend attached and lets the test decide when
+//! frames are rendered. Once the program has drawn what it's supposed to, the display can be
+//! compared against a "golden" PNG image that was saved from a known-good run:
+//!
+//! ```no_run
+//! use vex_sdk_desktop::testing::DisplayTest;
+//!
+//! let mut test = DisplayTest::start("auton-selector", || {
+//!     // draw the auton selector...
+//! });
+//!
+//! test.advance_frames(10);
+//! test.assert_matches_golden("tests/golden/auton_selector.png");
+//! ```
+//!
+//! If the display doesn't match, the actual frame and an image highlighting the differences are
+//! written next to the golden image as `<name>.actual.png` and `<name>.diff.png`. Running the
+//! tests with `ROBOSCOPE_BLESS=1` set replaces the golden images with the current output instead.
+//!
+//! The program header is excluded from comparisons because it shows the time since the program
+//! started.
+//!
+//! The display is shared by the entire process, so only one `DisplayTest` can run at a time.
+//! Starting a new one blocks until the previous one is dropped. Programs that don't return will
+//! keep running in the background after their test finishes, so they may interfere with later
+//! tests in the same process.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use anyhow::{Context, bail};
+use image::{Rgb, RgbImage};
+use parking_lot::{Mutex, MutexGuard};
+use roboscope_ipc::DISPLAY_UPDATE_PERIOD;
+use tracing::{debug, info};
+
+use crate::{
+    canvas::{CANVAS, Canvas, HEIGHT, Point, Rect, WIDTH},
+    display::{DISPLAY, FRAME_FINISHED, SimDisplay},
+    journal::JOURNAL,
+};
+
+/// Held by the currently running [`DisplayTest`].
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// A program running on the simulated display, controlled by a test.
+pub struct DisplayTest {
+    user_code: Option<JoinHandle<()>>,
+    frame: u64,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl DisplayTest {
+    /// Reset the display and start running the given entrypoint on a new thread.
+    ///
+    /// No frames are rendered until [`DisplayTest::advance_frames`] is called. Any draw calls left
+    /// in the [journal](crate::journal) by previous tests are discarded.
+    pub fn start(name: &str, entrypoint: impl FnOnce() + Send + 'static) -> Self {
+        let lock = TEST_LOCK.lock();
+
+        *CANVAS.lock() = Canvas::new();
+
+        let mut display = SimDisplay::new();
+        display.set_program_name(name);
+        *DISPLAY.lock() = display;
+
+        JOURNAL.lock().clear();
+
+        debug!(name, "Starting display test");
+
+        Self {
+            user_code: Some(thread::spawn(entrypoint)),
+            frame: 0,
+            _lock: lock,
+        }
+    }
+
+    /// Render the given number of frames at the simulated display's refresh rate.
+    ///
+    /// The program keeps running in the background while frames are rendered, so this is how a
+    /// test gives it time to draw.
+    pub fn advance_frames(&mut self, count: u32) {
+        let mut next_frame = Instant::now();
+
+        for _ in 0..count {
+            next_frame += *DISPLAY_UPDATE_PERIOD;
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+
+            let mut disp = DISPLAY.lock();
+            disp.render();
+            disp.take_dirty();
+
+            FRAME_FINISHED.notify_all();
+            self.frame += 1;
+        }
+    }
+
+    /// Get the number of frames rendered so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Returns `true` if the program's entrypoint has returned or panicked.
+    pub fn is_finished(&self) -> bool {
+        self.user_code
+            .as_ref()
+            .is_none_or(|user_code| user_code.is_finished())
+    }
+
+    /// Wait for the program's entrypoint to return, rendering frames while it runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program panicked.
+    pub fn run_to_completion(&mut self) {
+        while !self.is_finished() {
+            self.advance_frames(1);
+        }
+
+        // Render the program's final changes.
+        self.advance_frames(1);
+
+        if let Some(user_code) = self.user_code.take()
+            && user_code.join().is_err()
+        {
+            panic!("Program panicked during display test");
+        }
+    }
+
+    /// Save the current contents of the display as a PNG image.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        DISPLAY.lock().save_png(path)
+    }
+
+    /// Compare the display with the golden image at the given path.
+    ///
+    /// If `ROBOSCOPE_BLESS` is set, the golden image is replaced with the current contents of the
+    /// display instead. Otherwise, an error is returned if the golden image is missing or
+    /// doesn't match.
+    pub fn check_golden(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.check_golden_with(path.as_ref(), is_bless_mode())
+    }
+
+    fn check_golden_with(&self, path: &Path, bless: bool) -> anyhow::Result<()> {
+        let display = DISPLAY.lock();
+
+        if bless {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            display.save_png(path)?;
+            info!(path = %path.display(), "Blessed golden image");
+            return Ok(());
+        }
+
+        if !path.exists() {
+            bail!(
+                "Golden image {} doesn't exist (run with ROBOSCOPE_BLESS=1 to create it)",
+                path.display()
+            );
+        }
+
+        let golden = image::open(path)
+            .with_context(|| format!("Failed to load golden image {}", path.display()))?
+            .into_rgb8();
+
+        if golden.dimensions() != (WIDTH, HEIGHT) {
+            bail!(
+                "Golden image {} is {}x{}, expected {WIDTH}x{HEIGHT}",
+                path.display(),
+                golden.width(),
+                golden.height(),
+            );
+        }
+
+        let compared_region = if display.header_hidden {
+            Rect::FULL_CLIP
+        } else {
+            Rect::USER_CLIP
+        };
+
+        let mut diff = RgbImage::new(WIDTH, HEIGHT);
+        let mut mismatched_pixels = 0;
+
+        for point in Rect::FULL_CLIP.pixels() {
+            let actual = pixel_at(&display, point);
+            let expected = *golden.get_pixel(point.x as u32, point.y as u32);
+
+            let diff_pixel = if !point.is_inside(compared_region) {
+                Rgb([0, 0, 0])
+            } else if actual == expected {
+                // Show matching pixels as a dimmed version of the image for context.
+                Rgb(actual.0.map(|channel| channel / 4))
+            } else {
+                mismatched_pixels += 1;
+                Rgb([0xFF, 0x00, 0x00])
+            };
+
+            diff.put_pixel(point.x as u32, point.y as u32, diff_pixel);
+        }
+
+        if mismatched_pixels == 0 {
+            return Ok(());
+        }
+
+        let actual_path = sibling_path(path, "actual");
+        let diff_path = sibling_path(path, "diff");
+        display.save_png(&actual_path)?;
+        diff.save(&diff_path)?;
+
+        bail!(
+            "Display doesn't match golden image {} ({mismatched_pixels} pixels differ)\n\
+             actual: {}\n\
+             diff: {}\n\
+             (run with ROBOSCOPE_BLESS=1 to update the golden image)",
+            path.display(),
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+
+    /// Assert that the display matches the golden image at the given path.
+    ///
+    /// See [`DisplayTest::check_golden`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the display doesn't match the golden image.
+    #[track_caller]
+    pub fn assert_matches_golden(&self, path: impl AsRef<Path>) {
+        if let Err(error) = self.check_golden(path) {
+            panic!("{error:#}");
+        }
+    }
+}
+
+/// Returns `true` if golden images should be updated instead of compared.
+fn is_bless_mode() -> bool {
+    env::var("ROBOSCOPE_BLESS").is_ok_and(|bless| !bless.is_empty() && bless != "0")
+}
+
+fn pixel_at(display: &SimDisplay, point: Point) -> Rgb<u8> {
+    let [_, r, g, b] = display.buffer[(point.y * WIDTH as i32 + point.x) as usize].to_be_bytes();
+    Rgb([r, g, b])
+}
+
+/// Get a path next to the given one with an extra extension, e.g. `img.png` -> `img.diff.png`.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{suffix}.png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::sdk::{vexDisplayForegroundColor, vexDisplayRectFill};
+
+    /// A temporary directory for the golden images of a test, which is removed when it's dropped.
+    struct GoldenDir(PathBuf);
+
+    impl GoldenDir {
+        fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for GoldenDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Get an empty directory for the golden images of a test.
+    fn golden_dir(name: &str) -> GoldenDir {
+        let dir = env::temp_dir().join(format!("roboscope-golden-{}-{name}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        GoldenDir(dir)
+    }
+
+    fn draw_square(color: u32) -> DisplayTest {
+        let mut test = DisplayTest::start("golden-test", move || {
+            vexDisplayForegroundColor(color);
+            vexDisplayRectFill(100, 50, 149, 99);
+        });
+        test.run_to_completion();
+        test
+    }
+
+    #[test]
+    fn bless_then_compare() {
+        let dir = golden_dir("round-trip");
+        let golden = dir.join("square.png");
+
+        let test = draw_square(0x00_80_FF);
+        test.check_golden_with(&golden, true).unwrap();
+        assert!(golden.exists());
+        drop(test);
+
+        let test = draw_square(0x00_80_FF);
+        test.check_golden_with(&golden, false).unwrap();
+        assert!(!sibling_path(&golden, "actual").exists());
+        assert!(!sibling_path(&golden, "diff").exists());
+    }
+
+    #[test]
+    fn mismatch_writes_actual_and_diff() {
+        let dir = golden_dir("mismatch");
+        let golden = dir.join("square.png");
+
+        let test = draw_square(0x00_80_FF);
+        test.check_golden_with(&golden, true).unwrap();
+        drop(test);
+
+        let test = draw_square(0xFF_80_00);
+        let error = test.check_golden_with(&golden, false).unwrap_err();
+        assert!(error.to_string().contains("pixels differ"), "{error}");
+
+        let actual = image::open(sibling_path(&golden, "actual")).unwrap().into_rgb8();
+        let expected = image::open(&golden).unwrap().into_rgb8();
+        let diff = image::open(sibling_path(&golden, "diff")).unwrap().into_rgb8();
+
+        let display = DISPLAY.lock();
+        let changed: Vec<Point> = Rect::USER_CLIP
+            .pixels()
+            .filter(|point| {
+                expected.get_pixel(point.x as u32, point.y as u32) != &pixel_at(&display, *point)
+            })
+            .collect();
+        assert!(!changed.is_empty());
+
+        for point in Rect::USER_CLIP.pixels() {
+            let (x, y) = (point.x as u32, point.y as u32);
+            assert_eq!(actual.get_pixel(x, y), &pixel_at(&display, point));
+
+            let expected_diff = if changed.contains(&point) {
+                Rgb([0xFF, 0x00, 0x00])
+            } else {
+                Rgb(pixel_at(&display, point).0.map(|channel| channel / 4))
+            };
+            assert_eq!(diff.get_pixel(x, y), &expected_diff);
+        }
+    }
+
+    #[test]
+    fn missing_golden_image() {
+        let dir = golden_dir("missing");
+        let golden = dir.join("square.png");
+
+        let test = draw_square(0x00_80_FF);
+        let error = test.check_golden_with(&golden, false).unwrap_err();
+        assert!(error.to_string().contains("doesn't exist"), "{error}");
+    }
+}
@@ -3,7 +3,7 @@
 use std::{
     mem,
     num::NonZeroU32,
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::LazyLock,
     thread,
@@ -11,33 +11,174 @@ use std::{
 };
 
 use anyhow::{Context as _, Result, anyhow};
+use clap::Parser;
 use fast_image_resize::{
     ResizeAlg, ResizeOptions, Resizer,
     images::{TypedImage, TypedImageRef},
     pixels::U8x4,
 };
 use roboscope_ipc::{
-    Config, DISPLAY_HEIGHT, DISPLAY_WIDTH, DisplayFrame, PubSubFactory, Sample, SimServices, Subscriber
+    Config, ControlCommand, DEFAULT_ROBOT, DISPLAY_HEIGHT, DISPLAY_UPDATE_PERIOD, DISPLAY_WIDTH,
+    DisplayFrame, IpcOptions, PubSubFactory, Publisher, Sample, SimServices, Subscriber,
+    TouchInput,
+    screenshot::{self, timestamped_name},
 };
 use softbuffer::{Context, Surface};
-use tracing::{debug, error, trace};
+use tracing::{debug, error, info, trace};
 use winit::{
     application::ApplicationHandler,
-    dpi::LogicalSize,
+    dpi::{LogicalSize, PhysicalSize},
     event::{ElementState, MouseButton, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop, OwnedDisplayHandle},
+    keyboard::{Key, ModifiersState, NamedKey},
     window::{Theme, Window, WindowId},
 };
 
+use crate::recorder::{Recorder, RecordingFormat};
+
 #[cfg(target_os = "macos")]
 mod macos;
+mod recorder;
+mod terminal;
 
 const WINDOW_SIZE: LogicalSize<f64> = LogicalSize::new(480.0, 272.0);
+const WINDOW_TITLE: &str = "VEX V5 Simulator";
 
 type DisplayCtx = Context<OwnedDisplayHandle>;
 
+/// Shows the display of a running Roboscope simulator.
+///
+/// Press R in the viewer window to start or stop recording the display, S to save a screenshot
+/// (or Shift+S to also save a copy scaled to the window size), and F5 to restart the program.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Args {
+    /// Record the display to the given .gif, .png or .apng file, starting immediately.
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Stop recording after the given number of seconds.
+    #[arg(long, value_name = "SECONDS", requires = "record")]
+    duration: Option<f64>,
+
+    /// Record without opening a window. Recording stops after `--duration` or when the viewer is
+    /// interrupted.
+    #[arg(long, requires = "record")]
+    headless: bool,
+
+    /// The format of recordings started with the R key.
+    #[arg(long, value_enum, default_value_t = RecordingFormat::Gif)]
+    record_format: RecordingFormat,
+
+    /// Draw the display in the terminal instead of opening a window. Clicking on the display
+    /// touches the screen.
+    #[arg(long, conflicts_with_all = ["record", "headless"])]
+    terminal: bool,
+
+    /// Show the display of the robot with the given ID. This can be left out when only one
+    /// simulator is running.
+    #[arg(long, value_name = "ID")]
+    robot: Option<String>,
+
+    /// List the IDs of the robots which have a simulator running, then exit.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "record",
+            "duration",
+            "headless",
+            "record_format",
+            "terminal",
+            "robot",
+        ]
+    )]
+    list_robots: bool,
+
+    /// Only connect to simulators in the given IPC domain (overrides `ROBOSCOPE_IPC_DOMAIN`).
+    #[arg(long, value_name = "NAME")]
+    ipc_domain: Option<String>,
+
+    /// The directory iceoryx2 keeps its shared files in (overrides `ROBOSCOPE_IPC_ROOT`).
+    #[arg(long, value_name = "PATH")]
+    ipc_root: Option<PathBuf>,
+}
+
+impl Args {
+    fn record_duration(&self) -> Result<Option<Duration>> {
+        self.duration
+            .map(|secs| Duration::try_from_secs_f64(secs).context("Invalid recording duration"))
+            .transpose()
+    }
+
+    fn robot(&self) -> &str {
+        self.robot.as_deref().unwrap_or(DEFAULT_ROBOT)
+    }
+
+    /// Build the IPC config from the `ROBOSCOPE_IPC_*` environment variables and the IPC options.
+    fn ipc_config(&self) -> Result<Config> {
+        let mut options = IpcOptions::from_env()?;
+        if let Some(domain) = &self.ipc_domain {
+            options.domain = Some(domain.clone());
+        }
+        if let Some(root) = &self.ipc_root {
+            options.root_path = Some(root.clone());
+        }
+
+        Ok(options.config()?)
+    }
+}
+
 fn main() -> Result<()> {
-    ViewerApp::start()
+    let mut args = Args::parse();
+
+    let ipc_config = args.ipc_config()?;
+
+    if args.list_robots {
+        for robot in SimServices::running_robots(&ipc_config)? {
+            println!("{robot}");
+        }
+
+        return Ok(());
+    }
+
+    args.robot = Some(SimServices::find_robot(args.robot.as_deref(), &ipc_config)?);
+
+    if args.headless {
+        record_headless(&args, &ipc_config)
+    } else if args.terminal {
+        terminal::run(args.robot(), &ipc_config)
+    } else {
+        ViewerApp::start(args, &ipc_config)
+    }
+}
+
+/// Record the display to a file without opening a window.
+fn record_headless(args: &Args, ipc_config: &Config) -> Result<()> {
+    let path = args.record.clone().expect("--headless requires --record");
+    let duration = args.record_duration()?;
+
+    let ipc = SimServices::join(Some("viewer"), args.robot(), ipc_config)?;
+    let subscriber = ipc.display_frames()?.subscriber_builder().create()?;
+    let listener = ipc.display_frame_events()?.listener_builder().create()?;
+
+    let mut recorder = Recorder::create(path)?;
+    let started = Instant::now();
+    eprintln!("Recording to {}", recorder.path().display());
+
+    while ipc.wait_for_data(&listener, *DISPLAY_UPDATE_PERIOD)? {
+        while let Some(frame) = subscriber.receive()? {
+            recorder.push(&frame)?;
+        }
+
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break;
+        }
+    }
+
+    let path = recorder.finish()?;
+    eprintln!("Saved recording to {}", path.display());
+
+    Ok(())
 }
 
 pub struct ViewerApp {
@@ -45,23 +186,40 @@ pub struct ViewerApp {
     context: DisplayCtx,
     last_frame_time: Option<Instant>,
     subscriber: Option<Subscriber<DisplayFrame>>,
+    touch_publisher: Option<Publisher<TouchInput>>,
+    control_publisher: Option<Publisher<ControlCommand>>,
+    args: Args,
 }
 
 impl ViewerApp {
-    pub fn start() -> Result<()> {
-        let ipc = SimServices::join(Some("viewer"), &Config::default())?;
+    pub fn start(args: Args, ipc_config: &Config) -> Result<()> {
+        let ipc = SimServices::join(Some("viewer"), args.robot(), ipc_config)?;
         let subscriber = ipc.display_frames()?.subscriber_builder().create()?;
+        let touch_publisher = ipc.touch_input()?.publisher_builder().create()?;
+        let control_publisher = ipc.control()?.publisher_builder().create()?;
 
         let event_loop = EventLoop::with_user_event().build().unwrap();
 
         let display = event_loop.owned_display_handle();
-        let mut simulator = ViewerApp::new(display, subscriber)?;
+        let mut simulator = ViewerApp::new(
+            display,
+            subscriber,
+            touch_publisher,
+            control_publisher,
+            args,
+        )?;
         event_loop.run_app(&mut simulator)?;
 
         Ok(())
     }
 
-    fn new(display: OwnedDisplayHandle, subscriber: Subscriber<DisplayFrame>) -> Result<Self> {
+    fn new(
+        display: OwnedDisplayHandle,
+        subscriber: Subscriber<DisplayFrame>,
+        touch_publisher: Publisher<TouchInput>,
+        control_publisher: Publisher<ControlCommand>,
+        args: Args,
+    ) -> Result<Self> {
         let context = DisplayCtx::new(display)
             .map_err(|e| anyhow!(e.to_string()))
             .context("Failed to create display rendering context")?;
@@ -71,6 +229,9 @@ impl ViewerApp {
             context,
             last_frame_time: None,
             subscriber: Some(subscriber),
+            touch_publisher: Some(touch_publisher),
+            control_publisher: Some(control_publisher),
+            args,
         })
     }
 
@@ -90,9 +251,25 @@ impl ViewerApp {
 impl ApplicationHandler<()> for ViewerApp {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.sim_display.is_none() {
-            match SimDisplayWindow::open(event_loop, &self.context, self.subscriber.take().unwrap())
-            {
-                Ok(sim_display) => self.sim_display = Some(sim_display),
+            match SimDisplayWindow::open(
+                event_loop,
+                &self.context,
+                self.subscriber.take().unwrap(),
+                self.touch_publisher.take().unwrap(),
+                self.control_publisher.take().unwrap(),
+                self.args.record_format,
+            ) {
+                Ok(mut sim_display) => {
+                    if let Some(path) = self.args.record.take() {
+                        let duration = self.args.record_duration().unwrap_or_else(|error| {
+                            error!(%error, "Ignoring recording duration");
+                            None
+                        });
+                        sim_display.start_recording(path, duration);
+                    }
+
+                    self.sim_display = Some(sim_display);
+                }
                 Err(error) => error!(%error, "Failed to open VEX V5 Display window"),
             }
         }
@@ -115,8 +292,10 @@ impl ApplicationHandler<()> for ViewerApp {
                     trace!(measured_period = ?now - last, "Frame time");
                 }
 
-                if let Some(d) = &mut self.sim_display {
-                    d.queue_redraw();
+                if let Some(d) = &mut self.sim_display
+                    && let Err(error) = d.queue_redraw()
+                {
+                    error!(%error, "Failed to receive display frames");
                 }
             }
             _ => {}
@@ -143,13 +322,22 @@ pub struct SimDisplayWindow {
     surface: Surface<OwnedDisplayHandle, Rc<Window>>,
     subscriber: Subscriber<DisplayFrame>,
     last_frame: Option<Sample<DisplayFrame>>,
+    touch_publisher: Publisher<TouchInput>,
+    touch: TouchInput,
+    control_publisher: Publisher<ControlCommand>,
 
     scale_factor: f64,
+    fb_size: PhysicalSize<u32>,
+    modifiers: ModifiersState,
 
-    // A frame has been explicitly requested by the app; the next redraw should autorender the
-    // canvas, update the program header, notify vexDisplayRender callers, etc. instead of just
-    // scaling the previous rendered frame.
-    has_scheduled_frame: bool,
+    recorder: Option<Recorder>,
+    record_format: RecordingFormat,
+    /// When the current recording should automatically stop, if it was started with a duration.
+    stop_recording_at: Option<Instant>,
+
+    // The window's framebuffer is out of date, either because a frame with changes was received or
+    // because the window was resized.
+    needs_redraw: bool,
 }
 
 impl SimDisplayWindow {
@@ -157,6 +345,9 @@ impl SimDisplayWindow {
         event_loop: &ActiveEventLoop,
         context: &DisplayCtx,
         subscriber: Subscriber<DisplayFrame>,
+        touch_publisher: Publisher<TouchInput>,
+        control_publisher: Publisher<ControlCommand>,
+        record_format: RecordingFormat,
     ) -> Result<Self> {
         debug!("Opening V5 display window");
 
@@ -168,7 +359,7 @@ impl SimDisplayWindow {
             .with_min_inner_size(WINDOW_SIZE)
             .with_inner_size(WINDOW_SIZE)
             .with_theme(Some(Theme::Dark))
-            .with_title("VEX V5 Simulator");
+            .with_title(WINDOW_TITLE);
 
         let window = Rc::new(event_loop.create_window(attrs)?);
 
@@ -184,11 +375,19 @@ impl SimDisplayWindow {
 
         Ok(Self {
             surface,
-            window,
             subscriber,
             last_frame: None,
+            touch_publisher,
+            touch: TouchInput::default(),
+            control_publisher,
             scale_factor: 1.0,
-            has_scheduled_frame: true,
+            fb_size: window.inner_size(),
+            modifiers: ModifiersState::empty(),
+            recorder: None,
+            record_format,
+            stop_recording_at: None,
+            needs_redraw: true,
+            window,
         })
     }
 
@@ -196,8 +395,44 @@ impl SimDisplayWindow {
     pub fn handle_event(&mut self, event_loop: &ActiveEventLoop, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
+                self.stop_recording();
                 event_loop.exit();
             }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && !event.repeat =>
+            {
+                match &event.logical_key {
+                    Key::Character(ch) if ch.eq_ignore_ascii_case("r") => {
+                        self.toggle_recording();
+                    }
+                    Key::Character(ch) if ch.eq_ignore_ascii_case("s") => {
+                        self.save_screenshot(self.modifiers.shift_key());
+                    }
+                    Key::Named(NamedKey::F5) => self.send_restart(),
+                    _ => {}
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.touch.x = (position.x * self.scale_factor) as i32;
+                self.touch.y = (position.y * self.scale_factor) as i32;
+
+                // Only drags need to be sent, since the simulator ignores the position of the
+                // cursor when the screen isn't pressed.
+                if self.touch.pressed {
+                    self.send_touch();
+                }
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.touch.pressed = state == ElementState::Pressed;
+                self.send_touch();
+            }
             WindowEvent::RedrawRequested => {
                 self.redraw().unwrap();
             }
@@ -226,6 +461,8 @@ impl SimDisplayWindow {
                 }
 
                 self.scale_factor = WINDOW_SIZE.width / fb_dims.width as f64;
+                self.fb_size = fb_dims;
+                self.needs_redraw = true;
 
                 // Scale the framebuffer to the window.
                 self.surface
@@ -239,22 +476,144 @@ impl SimDisplayWindow {
         }
     }
 
-    pub fn queue_redraw(&mut self) {
-        self.has_scheduled_frame = true;
-        self.window.request_redraw();
+    /// Receive any new frames from the simulator, then schedule a redraw of the window if they
+    /// changed the display.
+    pub fn queue_redraw(&mut self) -> Result<()> {
+        while let Some(frame) = self.subscriber.receive()? {
+            let last_sequence = self.last_frame.as_ref().map(|last| last.info.sequence);
+
+            if !frame.info.changed_since(last_sequence).is_empty() {
+                self.needs_redraw = true;
+            }
+
+            trace!(info = ?frame.info, "Received frame");
+
+            if let Some(recorder) = &mut self.recorder
+                && let Err(error) = recorder.push(&frame)
+            {
+                error!(%error, "Failed to record frame, stopping recording");
+                self.recorder = None;
+                self.update_title();
+            }
+
+            self.last_frame = Some(frame);
+        }
+
+        if self
+            .stop_recording_at
+            .is_some_and(|stop_at| Instant::now() >= stop_at)
+        {
+            self.stop_recording();
+        }
+
+        if self.needs_redraw {
+            self.window.request_redraw();
+        }
+
+        Ok(())
     }
 
     pub fn window_id(&self) -> WindowId {
         self.window.id()
     }
 
-    /// Scale the display's contents to the size of the window, then write them to the framebuffer.
+    /// Start recording the display to the given file, optionally stopping after a duration.
+    pub fn start_recording(&mut self, path: PathBuf, duration: Option<Duration>) {
+        self.stop_recording();
+
+        match Recorder::create(path) {
+            Ok(recorder) => {
+                info!(path = %recorder.path().display(), "Recording display");
+                self.recorder = Some(recorder);
+                self.stop_recording_at = duration.map(|duration| Instant::now() + duration);
+            }
+            Err(error) => error!(%error, "Failed to start recording"),
+        }
+
+        self.update_title();
+    }
+
+    /// Stop the current recording (if there is one) and save it.
+    pub fn stop_recording(&mut self) {
+        self.stop_recording_at = None;
+
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(path) => info!(path = %path.display(), "Saved recording"),
+                Err(error) => error!(%error, "Failed to save recording"),
+            }
+        }
+
+        self.update_title();
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+        } else {
+            let name = timestamped_name("recording");
+            let path = PathBuf::from(format!("{name}.{}", self.record_format.extension()));
+            self.start_recording(path, None);
+        }
+    }
+
+    /// Save the most recent frame to a timestamped PNG in the current directory, optionally along
+    /// with a copy scaled to the size of the window.
+    pub fn save_screenshot(&self, include_scaled: bool) {
+        let Some(frame) = &self.last_frame else {
+            info!("No frames have been received yet, not saving a screenshot");
+            return;
+        };
+
+        let name = timestamped_name("screenshot");
+        let path = PathBuf::from(format!("{name}.png"));
+
+        let mut result = screenshot::save_png(&frame.buffer, &path);
+        if include_scaled && result.is_ok() {
+            let (width, height) = (self.fb_size.width, self.fb_size.height);
+            result = screenshot::save_scaled_png(
+                &frame.buffer,
+                width,
+                height,
+                format!("{name}-scaled.png"),
+            );
+        }
+
+        match result {
+            Ok(()) => info!(path = %path.display(), "Saved screenshot"),
+            Err(error) => error!(%error, "Failed to save screenshot"),
+        }
+    }
+
+    fn send_touch(&self) {
+        if let Err(error) = self.touch_publisher.send_copy(self.touch) {
+            error!(%error, "Failed to send touch input");
+        }
+    }
+
+    fn send_restart(&self) {
+        info!("Restarting program");
+        if let Err(error) = self.control_publisher.send_copy(ControlCommand::Restart) {
+            error!(%error, "Failed to send restart command");
+        }
+    }
+
+    fn update_title(&self) {
+        if self.recorder.is_some() {
+            self.window.set_title(&format!("{WINDOW_TITLE} (Recording)"));
+        } else {
+            self.window.set_title(WINDOW_TITLE);
+        }
+    }
+
+    /// Scale the most recent frame to the size of the window, then write it to the framebuffer.
     pub fn redraw(&mut self) -> Result<()> {
-        let next_frame = self.subscriber.receive()?;
-        let Some(frame) = next_frame.as_ref().or(self.last_frame.as_ref()) else {
+        let Some(frame) = &self.last_frame else {
             return Ok(());
         };
 
+        self.needs_redraw = false;
+
         let mut window_buffer = self.surface.buffer_mut().unwrap();
         let width = window_buffer.width().get();
         let height = window_buffer.height().get();
@@ -283,10 +642,6 @@ impl SimDisplayWindow {
         self.window.pre_present_notify();
         window_buffer.present().unwrap();
 
-        if next_frame.is_some() {
-            self.last_frame = next_frame;
-        }
-
         Ok(())
     }
 }
@@ -0,0 +1,426 @@
+//! Recording display frames to animated GIF or APNG files.
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use image::{
+    Delay, Frame, RgbaImage,
+    codecs::gif::{GifEncoder, Repeat},
+};
+use roboscope_ipc::{DISPLAY_HEIGHT, DISPLAY_UPDATE_PERIOD, DISPLAY_WIDTH, DisplayFrame};
+use tracing::debug;
+
+/// Speed of the GIF color quantizer, from 1 (best quality) to 30 (fastest). Frames are encoded
+/// while they are being received, so this needs to be fast enough to keep up with the display.
+const GIF_ENCODE_SPEED: i32 = 20;
+
+/// The animated image format used for a recording.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum RecordingFormat {
+    Gif,
+    Apng,
+}
+
+impl RecordingFormat {
+    /// Guess the format of a recording from its file extension.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("gif") => Ok(Self::Gif),
+            Some("png" | "apng") => Ok(Self::Apng),
+            _ => bail!(
+                "Can't record to {}: recordings must be .gif, .png or .apng files",
+                path.display()
+            ),
+        }
+    }
+
+    /// Get the file extension normally used for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+/// Writes the display frames it's given to an animated image, using the frames' timestamps for
+/// the delay between them.
+///
+/// Frames which don't change anything on the display are merged into the frame before them, so
+/// a recording of a mostly-static screen stays small.
+pub struct Recorder {
+    path: PathBuf,
+    sink: Sink,
+    /// The most recent frame that changed the display. It's held back until the next change is
+    /// received, since that's when its delay is known.
+    pending: Option<PendingFrame>,
+    last_sequence: Option<u64>,
+    latest_timestamp: Duration,
+    frame_count: usize,
+}
+
+enum Sink {
+    /// GIF frames are encoded as soon as they are complete.
+    Gif(GifEncoder<BufWriter<File>>),
+    /// APNG files need to know the number of frames before any are written, so the frames are
+    /// spooled to a temporary file until the recording is finished.
+    Apng(FrameSpool),
+}
+
+struct PendingFrame {
+    pixels: Box<[u32]>,
+    timestamp: Duration,
+}
+
+impl Recorder {
+    /// Start a new recording which will be saved to the given path.
+    ///
+    /// The format of the recording is chosen from the path's file extension.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let sink = match RecordingFormat::from_path(&path)? {
+            RecordingFormat::Gif => {
+                let file = File::create(&path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+
+                let mut encoder =
+                    GifEncoder::new_with_speed(BufWriter::new(file), GIF_ENCODE_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Sink::Gif(encoder)
+            }
+            RecordingFormat::Apng => Sink::Apng(FrameSpool::create()?),
+        };
+
+        debug!(path = %path.display(), "Started recording");
+
+        Ok(Self {
+            path,
+            sink,
+            pending: None,
+            last_sequence: None,
+            latest_timestamp: Duration::ZERO,
+            frame_count: 0,
+        })
+    }
+
+    /// Get the path the recording will be saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add a frame received from the simulator to the recording.
+    pub fn push(&mut self, frame: &DisplayFrame) -> Result<()> {
+        let changed = !frame.info.changed_since(self.last_sequence).is_empty();
+        self.last_sequence = Some(frame.info.sequence);
+        self.latest_timestamp = frame.info.timestamp();
+
+        if !changed {
+            return Ok(());
+        }
+
+        if let Some(pending) = self.pending.take() {
+            let delay = frame.info.timestamp().saturating_sub(pending.timestamp);
+            self.write_frame(pending.pixels, delay)?;
+        }
+
+        self.pending = Some(PendingFrame {
+            pixels: frame.buffer.as_slice().into(),
+            timestamp: frame.info.timestamp(),
+        });
+
+        Ok(())
+    }
+
+    /// Finish the recording and save it, returning the path it was saved to.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        if let Some(pending) = self.pending.take() {
+            let delay = self.latest_timestamp.saturating_sub(pending.timestamp);
+            self.write_frame(pending.pixels, delay)?;
+        }
+
+        match self.sink {
+            Sink::Gif(encoder) => {
+                // The GIF trailer is written when the encoder is dropped.
+                drop(encoder);
+
+                if self.frame_count == 0 {
+                    _ = fs::remove_file(&self.path);
+                    bail!("No frames were recorded");
+                }
+            }
+            Sink::Apng(spool) => write_apng(&self.path, spool, self.frame_count)?,
+        }
+
+        debug!(path = %self.path.display(), frames = self.frame_count, "Saved recording");
+        Ok(self.path)
+    }
+
+    fn write_frame(&mut self, pixels: Box<[u32]>, delay: Duration) -> Result<()> {
+        // Frames can't be shown for less than one refresh of the display, and a delay of zero
+        // is treated as "as slow as possible" by some GIF decoders.
+        let delay = delay.max(*DISPLAY_UPDATE_PERIOD);
+
+        match &mut self.sink {
+            Sink::Gif(encoder) => {
+                let rgba = pixels
+                    .iter()
+                    .flat_map(|pixel| {
+                        let [_, r, g, b] = pixel.to_be_bytes();
+                        [r, g, b, 0xFF]
+                    })
+                    .collect();
+                let image = RgbaImage::from_raw(DISPLAY_WIDTH, DISPLAY_HEIGHT, rgba)
+                    .expect("display dimensions are always valid");
+
+                let frame = Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(delay));
+                encoder.encode_frame(frame)?;
+            }
+            Sink::Apng(spool) => spool.push(&pixels, delay)?,
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+}
+
+/// The size of a frame's RGB data in an APNG file.
+const APNG_FRAME_LEN: usize = DISPLAY_WIDTH as usize * DISPLAY_HEIGHT as usize * 3;
+
+/// A temporary file holding the frames of an APNG recording, each stored as its delay in
+/// milliseconds followed by its RGB data. The file is removed when this is dropped.
+struct FrameSpool {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl FrameSpool {
+    fn create() -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "roboscope-recording-{}-{}.frames",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        ));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create temporary file {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    fn push(&mut self, pixels: &[u32], delay: Duration) -> Result<()> {
+        let millis = delay.as_millis().min(u16::MAX as u128) as u16;
+        self.writer.write_all(&millis.to_le_bytes())?;
+
+        for pixel in pixels {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            self.writer.write_all(&[r, g, b])?;
+        }
+
+        Ok(())
+    }
+
+    /// Finish writing frames, and open the file to read them back.
+    fn reader(&mut self) -> Result<BufReader<File>> {
+        self.writer.flush()?;
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to read temporary file {}", self.path.display()))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+impl Drop for FrameSpool {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            debug!(%error, path = %self.path.display(), "Failed to remove recording frames");
+        }
+    }
+}
+
+fn write_apng(path: &Path, mut spool: FrameSpool, frame_count: usize) -> Result<()> {
+    if frame_count == 0 {
+        bail!("No frames were recorded");
+    }
+
+    let mut frames = spool.reader()?;
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frame_count as u32, 0)?;
+
+    let mut writer = encoder.write_header()?;
+    let mut millis = [0; 2];
+    let mut rgb = vec![0; APNG_FRAME_LEN];
+
+    for _ in 0..frame_count {
+        frames.read_exact(&mut millis)?;
+        frames.read_exact(&mut rgb)?;
+
+        writer.set_frame_delay(u16::from_le_bytes(millis), 1000)?;
+        writer.write_image_data(&rgb)?;
+    }
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{AnimationDecoder, codecs::{gif::GifDecoder, png::PngDecoder}};
+    use roboscope_ipc::{DISPLAY_BUF_SIZE, FrameInfo, FrameRect};
+
+    use super::*;
+
+    const RED: u32 = 0xFF_00_00;
+    const BLUE: u32 = 0x00_00_FF;
+
+    /// A recording in the temporary directory, which is removed when it's dropped.
+    struct TempRecording(PathBuf);
+
+    impl TempRecording {
+        fn new(name: &str) -> Self {
+            Self(env::temp_dir().join(format!("roboscope-recorder-{}-{name}", process::id())))
+        }
+    }
+
+    impl Drop for TempRecording {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A frame filled with a single color, published `millis` after the program started.
+    fn frame(sequence: u64, millis: u64, color: u32, changed: bool) -> DisplayFrame {
+        DisplayFrame {
+            info: FrameInfo {
+                sequence,
+                timestamp_us: millis * 1000,
+                dirty: if changed {
+                    FrameRect::FULL
+                } else {
+                    FrameRect::default()
+                },
+                header_visible: true,
+                autorender: true,
+            },
+            buffer: [color; DISPLAY_BUF_SIZE],
+        }
+    }
+
+    /// Record a red screen for 200ms followed by a blue screen for 300ms, with unchanged frames in
+    /// between.
+    fn record(recorder: &mut Recorder) {
+        recorder.push(&frame(0, 0, RED, true)).unwrap();
+        recorder.push(&frame(1, 100, RED, false)).unwrap();
+        recorder.push(&frame(2, 200, BLUE, true)).unwrap();
+        recorder.push(&frame(3, 350, BLUE, false)).unwrap();
+        recorder.push(&frame(4, 500, BLUE, false)).unwrap();
+    }
+
+    fn delay_ms(frame: &Frame) -> u32 {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        numerator / denominator
+    }
+
+    fn assert_color(frame: &Frame, color: u32) {
+        let [_, r, g, b] = color.to_be_bytes();
+        let pixel = frame.buffer().get_pixel(10, 10).0;
+
+        // GIF frames go through a color quantizer, so they may not be exact.
+        for (actual, expected) in pixel.into_iter().zip([r, g, b, 0xFF]) {
+            assert!(actual.abs_diff(expected) <= 8, "{pixel:?} isn't {color:06X}");
+        }
+    }
+
+    #[test]
+    fn gif_drops_unchanged_frames() {
+        let recording = TempRecording::new("frames.gif");
+        let mut recorder = Recorder::create(&recording.0).unwrap();
+        record(&mut recorder);
+        recorder.finish().unwrap();
+
+        let file = BufReader::new(File::open(&recording.0).unwrap());
+        let frames = GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_color(&frames[0], RED);
+        assert_eq!(delay_ms(&frames[0]), 200);
+        assert_color(&frames[1], BLUE);
+        assert_eq!(delay_ms(&frames[1]), 300);
+    }
+
+    #[test]
+    fn apng_is_spooled() {
+        let recording = TempRecording::new("frames.png");
+        let mut recorder = Recorder::create(&recording.0).unwrap();
+
+        let Sink::Apng(spool) = &recorder.sink else {
+            panic!("PNG recordings should be spooled");
+        };
+        let spool_path = spool.path.clone();
+
+        record(&mut recorder);
+        assert_eq!(recorder.frame_count, 1);
+        assert!(!recording.0.exists());
+
+        recorder.finish().unwrap();
+        assert!(!spool_path.exists());
+
+        let file = BufReader::new(File::open(&recording.0).unwrap());
+        let decoder = PngDecoder::new(file).unwrap().apng().unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_color(&frames[0], RED);
+        assert_eq!(delay_ms(&frames[0]), 200);
+        assert_color(&frames[1], BLUE);
+        assert_eq!(delay_ms(&frames[1]), 300);
+    }
+
+    #[test]
+    fn short_frames_last_one_display_update() {
+        let recording = TempRecording::new("short.png");
+        let mut recorder = Recorder::create(&recording.0).unwrap();
+        recorder.push(&frame(0, 0, RED, true)).unwrap();
+        recorder.push(&frame(1, 1, BLUE, true)).unwrap();
+        recorder.finish().unwrap();
+
+        let file = BufReader::new(File::open(&recording.0).unwrap());
+        let decoder = PngDecoder::new(file).unwrap().apng().unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+
+        let min_delay = DISPLAY_UPDATE_PERIOD.as_millis() as u32;
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| delay_ms(frame) >= min_delay));
+    }
+
+    #[test]
+    fn empty_recording() {
+        let recording = TempRecording::new("empty.gif");
+        let recorder = Recorder::create(&recording.0).unwrap();
+
+        let error = recorder.finish().unwrap_err();
+        assert_eq!(error.to_string(), "No frames were recorded");
+        assert!(!recording.0.exists());
+    }
+}
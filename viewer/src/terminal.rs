@@ -0,0 +1,53 @@
+//! Viewer mode which draws the display in a terminal.
+//!
+//! The display is drawn by [`roboscope_ipc::terminal`], which needs a terminal that supports
+//! truecolor escape codes. Clicking and dragging with the left mouse button touches the screen, `r`
+//! restarts the program, and pressing `q`, `Esc` or `Ctrl+C` closes the viewer.
+
+use anyhow::Result;
+use roboscope_ipc::{
+    Config, ControlCommand, DISPLAY_UPDATE_PERIOD, DisplayFrame, Sample, SimServices, TouchInput,
+    terminal::{TerminalInput, TerminalScreen},
+};
+
+/// Show the display of a running simulator in the terminal until the user quits.
+pub fn run(robot: &str, ipc_config: &Config) -> Result<()> {
+    let ipc = SimServices::join(Some("viewer"), robot, ipc_config)?;
+    let frames = ipc.display_frames()?.subscriber_builder().create()?;
+    let frame_events = ipc.display_frame_events()?.listener_builder().create()?;
+    let touch = ipc.touch_input()?.publisher_builder().create()?;
+    let control = ipc.control()?.publisher_builder().create()?;
+
+    let mut screen = TerminalScreen::enter()?;
+    let mut last_frame: Option<Sample<DisplayFrame>> = None;
+
+    // The timeout keeps input responsive while no frames are being published.
+    while ipc.wait_for_data(&frame_events, *DISPLAY_UPDATE_PERIOD)? {
+        for input in screen.poll_input()? {
+            match input {
+                TerminalInput::Quit => return Ok(()),
+                TerminalInput::Restart => {
+                    control.send_copy(ControlCommand::Restart)?;
+                }
+                TerminalInput::Touch { x, y, pressed } => {
+                    touch.send_copy(TouchInput { x, y, pressed })?;
+                }
+            }
+        }
+
+        let mut changed = false;
+        while let Some(frame) = frames.receive()? {
+            let last_sequence = last_frame.as_ref().map(|last| last.info.sequence);
+            changed |= !frame.info.changed_since(last_sequence).is_empty();
+            last_frame = Some(frame);
+        }
+
+        if let Some(frame) = &last_frame
+            && (changed || screen.needs_redraw())
+        {
+            screen.draw(&frame.buffer)?;
+        }
+    }
+
+    Ok(())
+}